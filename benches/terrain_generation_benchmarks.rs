@@ -1,10 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-use crate::noise::noise_terrain;
-use crate::noise::NoiseType;
-
-mod terrain;
-mod noise;
+use ftt_terrain::noise::{noise_terrain, noise_terrain_par_with_seed, noise_terrain_with_seed, NoiseType};
+use ftt_terrain::terrain;
 
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("diamond square n = 8", |b| b.iter(|| terrain::midpoint_displacement(8, 0.75, 0.50, 85.0)));
@@ -14,5 +11,23 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("worley n = 8", |b| b.iter(|| noise_terrain(8, NoiseType::Worley)));
 }
 
-criterion_group!(benches, criterion_benchmark);
+// Serial against parallel noise generation on 4096 x 4096 maps.
+fn parallel_noise_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("noise n = 12");
+    group.sample_size(10);
+
+    let noise_types = [
+        ("perlin", NoiseType::Perlin),
+        ("simplex", NoiseType::Simplex),
+        ("worley", NoiseType::Worley),
+    ];
+    for (name, noise_type) in noise_types {
+        group.bench_function(format!("{} serial", name), |b| b.iter(|| noise_terrain_with_seed(black_box(12), noise_type, 42)));
+        group.bench_function(format!("{} parallel", name), |b| b.iter(|| noise_terrain_par_with_seed(black_box(12), noise_type, 42)));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark, parallel_noise_benchmark);
 criterion_main!(benches);
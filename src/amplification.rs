@@ -0,0 +1,190 @@
+use crate::fft_utils::{apply_fft_to_rect_grid, apply_high_pass_filter, apply_ifft_to_rect_grid, frequency_bin};
+use crate::resample::{fft_resample, ResampleWindow};
+use crate::terrain::midpoint_displacement_hurst_with_seed;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rustfft::num_complex::Complex;
+
+// Where the detail added on top of the upsampled heightmap comes from.
+#[derive(Clone, Copy)]
+pub enum DetailSource {
+    MidpointDisplacement,
+    Spectral,
+}
+
+#[derive(Clone, Copy)]
+pub struct AmplifyParams {
+    pub detail: DetailSource,
+    // Hurst exponent of the added detail, higher values give smoother detail.
+    pub hurst: f32,
+    // Scale of the detail. At 1.0 its standard deviation matches the average height difference between
+    // neighbouring samples of the coarse heightmap.
+    pub amplitude: f32,
+    // The same seed adds the same detail.
+    pub seed: u64,
+}
+
+// Upsamples a coarse heightmap by factor and adds fractal detail at the frequencies the coarse heightmap
+// can't represent, above its Nyquist frequency. Decimating the result back to the coarse size in the
+// frequency domain with fft_resample and ResampleWindow::None gives the coarse heightmap back: the band
+// below its Nyquist frequency is the coarse heightmap exactly, and all of the detail lies above it.
+pub fn amplify(coarse: &[Vec<f32>], factor: usize, params: &AmplifyParams) -> Vec<Vec<f32>> {
+    let mut fine = bicubic_upsample(coarse, factor);
+    if factor == 1 {
+        return fine;
+    }
+
+    let height = fine.len();
+    let width = fine[0].len();
+
+    // Bicubic interpolation smooths the band of the coarse heightmap a little and adds some of its own
+    // above it. Whatever it gets wrong below the coarse Nyquist frequency is put back spectrally.
+    let decimated = fft_resample(&fine, coarse[0].len(), coarse.len(), ResampleWindow::None);
+    let residual: Vec<Vec<f32>> = coarse.iter().zip(decimated.iter()).map(|(coarse_row, decimated_row)|
+        coarse_row.iter().zip(decimated_row.iter()).map(|(coarse, decimated)| coarse - decimated).collect()
+    ).collect();
+    let correction = fft_resample(&residual, width, height, ResampleWindow::None);
+    for (fine_row, correction_row) in fine.iter_mut().zip(correction.iter()) {
+        for (height, correction) in fine_row.iter_mut().zip(correction_row.iter()) {
+            *height += correction;
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(params.seed);
+
+    let mut spectrum = match params.detail {
+        DetailSource::Spectral => {
+            let mut spectrum: Vec<Vec<Complex<f32>>> = (0..height).map(|_|
+                (0..width).map(|_|
+                    Complex::new(rng.gen_range(-1.0..1.0), 0.0)
+                ).collect()
+            ).collect();
+
+            // Shape the white noise into fractional Brownian motion, whose amplitude falls off as f^-(H + 1).
+            // Frequencies are in cycles per sample so both axes agree when the heightmap isn't square.
+            apply_fft_to_rect_grid(&mut spectrum);
+            let lowest = 1.0 / width.max(height) as f32;
+            for (i, row) in spectrum.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    let frequency = ((frequency_bin(i, height) / height as f32).powi(2) + (frequency_bin(j, width) / width as f32).powi(2)).sqrt();
+                    *value *= frequency.max(lowest).powf(-(params.hurst + 1.0));
+                }
+            }
+            spectrum
+        }
+        DetailSource::MidpointDisplacement => {
+            // Smallest midpoint displacement heightmap that covers the result.
+            let mut n = 0;
+            while 2_usize.pow(n) + 1 < width.max(height) {
+                n += 1;
+            }
+
+            let detail = midpoint_displacement_hurst_with_seed(n, params.hurst, 1.0, true, rng.gen());
+            let mut spectrum: Vec<Vec<Complex<f32>>> = detail[..height].iter().map(|row|
+                row[..width].iter().map(|&height| Complex::new(height, 0.0)).collect()
+            ).collect();
+            apply_fft_to_rect_grid(&mut spectrum);
+            spectrum
+        }
+    };
+
+    // Band split: the coarse heightmap holds every frequency up to its Nyquist frequency, half its width
+    // and height in cycles per grid. Only what lies above that along either axis is kept as detail.
+    apply_high_pass_filter(&mut spectrum, coarse[0].len() as f32 / 2.0, coarse.len() as f32 / 2.0);
+    apply_ifft_to_rect_grid(&mut spectrum);
+
+    let detail_deviation = root_mean_square(spectrum.iter().flatten().map(|value| value.re));
+    if detail_deviation > 0.0 {
+        let scale = params.amplitude * coarse_roughness(coarse) / detail_deviation;
+        for (fine_row, detail_row) in fine.iter_mut().zip(spectrum.iter()) {
+            for (height, detail) in fine_row.iter_mut().zip(detail_row.iter()) {
+                *height += detail.re * scale;
+            }
+        }
+    }
+
+    fine
+}
+
+// Upsamples a heightmap by an integer factor with Catmull-Rom bicubic interpolation. The result is
+// factor times wider and taller, sample (i, j) of the input is sample (i * factor, j * factor) of the
+// result, and the samples past the last input row and column are extrapolated from the edge.
+pub fn bicubic_upsample(coarse: &[Vec<f32>], factor: usize) -> Vec<Vec<f32>> {
+    let coarse_height = coarse.len();
+    let coarse_width = coarse[0].len();
+
+    let sample = |i: isize, j: isize| -> f32 {
+        coarse[i.clamp(0, coarse_height as isize - 1) as usize][j.clamp(0, coarse_width as isize - 1) as usize]
+    };
+
+    // Interpolates rows first, then columns.
+    (0..coarse_height * factor).map(|y| {
+        let i = (y / factor) as isize;
+        let weights_y = catmull_rom_weights((y % factor) as f32 / factor as f32);
+
+        (0..coarse_width * factor).map(|x| {
+            let j = (x / factor) as isize;
+            let weights_x = catmull_rom_weights((x % factor) as f32 / factor as f32);
+
+            (0..4).map(|a| {
+                let row: f32 = (0..4).map(|b| weights_x[b] * sample(i + a as isize - 1, j + b as isize - 1)).sum();
+                weights_y[a] * row
+            }).sum()
+        }).collect()
+    }).collect()
+}
+
+// Weights of the 4 samples around a point at fraction t between the second and third sample.
+fn catmull_rom_weights(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        (-t3 + 2.0 * t2 - t) / 2.0,
+        (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+        (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+        (t3 - t2) / 2.0,
+    ]
+}
+
+// Root mean square height difference between neighbouring samples.
+fn coarse_roughness(coarse: &[Vec<f32>]) -> f32 {
+    let horizontal = coarse.iter().flat_map(|row| row.windows(2).map(|pair| pair[1] - pair[0]));
+    let vertical = coarse.windows(2).flat_map(|rows| rows[0].iter().zip(rows[1].iter()).map(|(a, b)| b - a));
+    root_mean_square(horizontal.chain(vertical))
+}
+
+fn root_mean_square(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0.0_f64, 0_usize), |(sum, count), value| (sum + (value as f64).powi(2), count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        (sum / count as f64).sqrt() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{hills, max_difference};
+
+    #[test]
+    fn downsampling_the_result_gives_the_input_back() {
+        let coarse = hills(16);
+        let upsampled = bicubic_upsample(&coarse, 4);
+
+        for detail in [DetailSource::Spectral, DetailSource::MidpointDisplacement] {
+            let amplified = amplify(&coarse, 4, &AmplifyParams { detail, hurst: 0.8, amplitude: 1.0, seed: 3 });
+            // The detail is there, and decimating the result removes all of it.
+            assert!(max_difference(&amplified, &upsampled) > 1.0);
+            assert!(max_difference(&fft_resample(&amplified, 16, 16, ResampleWindow::None), &coarse) < 1e-3);
+        }
+    }
+
+    #[test]
+    fn the_seed_decides_the_detail() {
+        let coarse = hills(16);
+        let params = AmplifyParams { detail: DetailSource::MidpointDisplacement, hurst: 0.8, amplitude: 1.0, seed: 3 };
+        assert_eq!(amplify(&coarse, 4, &params), amplify(&coarse, 4, &params));
+        assert_ne!(amplify(&coarse, 4, &params), amplify(&coarse, 4, &AmplifyParams { seed: 4, ..params }));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::noise::{noise_window, NoiseType};
+use crate::terrain::midpoint_displacement_chunk;
+
+// Generators that can produce an infinite world one chunk at a time.
+#[derive(Clone, Copy)]
+pub enum ChunkGenerator {
+    Noise(NoiseType),
+    MidpointDisplacement {
+        initial_roughness: f32,
+        roughness_factor: f32,
+        initial_max_height: f32,
+    },
+}
+
+// Generate the heightmap of chunk (cx, cy) of the world described by world_seed. The chunk covers
+// chunk_size cells and has chunk_size + 1 samples per side: its last row and column are the first
+// row and column of the chunks below and to the right of it, and both chunks agree on their values.
+// chunk_size has to be a power of two for midpoint displacement.
+pub fn generate_chunk(world_seed: u32, cx: i32, cy: i32, chunk_size: usize, generator: ChunkGenerator) -> Vec<Vec<f32>> {
+    match generator {
+        ChunkGenerator::Noise(noise_type) => {
+            // Noise is defined everywhere, so sampling it at world coordinates is already seamless.
+            let origin_x = cx as i64 * chunk_size as i64;
+            let origin_y = cy as i64 * chunk_size as i64;
+            noise_window(noise_type, world_seed, origin_x, origin_y, chunk_size + 1)
+        }
+        ChunkGenerator::MidpointDisplacement { initial_roughness, roughness_factor, initial_max_height } => {
+            assert!(chunk_size.is_power_of_two(), "midpoint displacement chunks must be a power of two wide");
+            let n = chunk_size.trailing_zeros();
+            midpoint_displacement_chunk(world_seed as u64, cx as i64, cy as i64, n, initial_roughness, roughness_factor, initial_max_height)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbouring_chunks_share_their_borders() {
+        let generators = [
+            ChunkGenerator::Noise(NoiseType::Perlin),
+            ChunkGenerator::MidpointDisplacement { initial_roughness: 0.75, roughness_factor: 0.5, initial_max_height: 45.0 },
+        ];
+        for generator in generators {
+            for (cx, cy) in [(0, 0), (-1, -1), (-3, 2)] {
+                let chunk = generate_chunk(7, cx, cy, 32, generator);
+                let right = generate_chunk(7, cx + 1, cy, 32, generator);
+                let below = generate_chunk(7, cx, cy + 1, 32, generator);
+
+                let last_column: Vec<f32> = chunk.iter().map(|row| row[32]).collect();
+                let first_column: Vec<f32> = right.iter().map(|row| row[0]).collect();
+                assert_eq!(last_column, first_column);
+                assert_eq!(chunk[32], below[0]);
+            }
+        }
+    }
+}
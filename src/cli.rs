@@ -0,0 +1,550 @@
+use crate::amplification::{amplify, AmplifyParams, DetailSource};
+use crate::comparison::compare;
+use crate::constraints::{apply_constraints, Constraints, ControlPoint};
+use crate::contours::{contours, contours_geojson, contours_svg};
+use crate::convolution::{box_kernel, convolve, gaussian_kernel, laplacian_kernel, unsharp_mask_kernel, BoundaryMode};
+use crate::correlation::{autocorrelation, correlation_lengths};
+use crate::drainage::{drainage_basins, flow_directions};
+use crate::filters::{apply_filter, Anisotropy, Filter, FilterShape};
+use crate::fractal_analysis::{dyadic_scales, power_spectrum, save_spectrum_image};
+use crate::heightmap_io::load_heightmap;
+use crate::landforms::{geomorphons, save_landform_png, topographic_position_index, GeomorphonParams};
+use crate::mesh::heightmap_mesh;
+use crate::multifractal::{lacunarity, multifractal_spectrum, Measure};
+use crate::noise::{noise_terrain_constrained, noise_terrain_par, noise_terrain_par_with_seed, NoiseType};
+use crate::peaks::find_peaks;
+use crate::random_fields::{gaussian_random_field, CovarianceModel, CovarianceParams};
+use crate::rasterizer::{render_mesh, RasterParams};
+use crate::resample::{fft_resample, ResampleWindow};
+use crate::shading::{hypsometric_tint, save_relief_image, HillshadeParams, ReliefParams, HYPSOMETRIC_RAMP};
+use crate::stats::terrain_stats;
+use crate::terrain;
+use serde::Serialize;
+use std::f32::consts::PI;
+use std::fs;
+
+// Command line analysis tools, run instead of the viewer when the program gets arguments:
+//
+//   ftt-terrain <command> [--generator <name> | --input <file>] [--n <exponent>] [--output <file>]
+
+const USAGE: &str = "Usage: ftt-terrain <command> [options]
+
+Commands:
+  multifractal   Generalized dimensions D(q) and singularity spectrum f(alpha) as CSV
+  lacunarity     Gliding box lacunarity curve as CSV
+  stats          Surface texture statistics as JSON
+  spectrum       Radially averaged power spectrum as CSV with the fitted exponent beta on every row, or
+                 the log magnitude spectrum as a PNG when --output ends in .png
+  correlation    Correlation lengths along the principal axes as JSON
+  compare        Similarity of the heightmap to --target as JSON
+  landforms      Geomorphon landform map as an indexed PNG, needs --output
+  tpi            Topographic position index at --radius, one row of the heightmap per line like --input reads
+  peaks          Peaks ranked by topographic prominence as JSON
+  basins         Drainage basins with their outlets, areas and reliefs as JSON, largest first
+  contours       Contour lines as GeoJSON, or as SVG when --output ends in .svg, every 5th an index contour
+  render         Shaded relief PNG with hillshade, hypsometric colors and slope shading, needs --output
+  preview        PNG of the terrain seen from the viewer's camera, in hypsometric colors, needs --output
+
+Options:
+  --generator <name>   midpoint, hurst, fft, anisotropic, field, perlin, simplex or worley (default fft)
+  --input <file>       Load the heightmap from a .png or a text file of heights instead of generating it
+  --target <name>      Generator name or file of the heightmap compare measures against
+  --height-scale <h>   Height of white in .png files (default 1)
+  --n <exponent>       Heightmaps are 2^n samples wide (default 8)
+  --seed <seed>        Seed of the midpoint, hurst, field, perlin, simplex and worley generators and of the detail
+                       --amplify adds (default random)
+  --covariance <name>  Covariance of the field generator: exponential, gaussian, spherical or
+                       matern:<smoothness> (default matern:1.5)
+  --point <x,y,h>      Fix the height at column x and row y of the heightmap, can be repeated
+  --mask <file>        Where the mask is 1, e.g. white in a .png, the heightmap has to match --mask-target
+  --mask-target <file> Heights the masked part of the heightmap has to match
+  --correlation-length <cells>
+                       Distance over which constraints blend into the terrain (default 1/8 of the width)
+  --amplify <factor>   Upsample the heightmap by the factor and add fractal detail above its resolution
+  --detail <name>      Detail --amplify adds, spectral or midpoint (default spectral)
+  --resample <w>x<h>   Resample the heightmap to w x h samples in the frequency domain, or to <w> x <w>
+  --resample-window <name>
+                       Edge treatment of --resample: none, hann, tukey or mirror (default mirror)
+  --filter <band>      Filter the heightmap with lowpass:<f>, highpass:<f> or bandpass:<low>-<high>,
+                       frequencies in cycles per sample up to 0.5
+  --filter-shape <name>
+                       Roll off of --filter: ideal, gaussian or butterworth (default butterworth)
+  --kernel <name>      Convolve the heightmap with gaussian:<sigma>, box:<radius>, unsharp:<sigma>,<amount>
+                       or laplacian
+  --boundary <name>    Samples --kernel reads past the edges: wrap, clamp, mirror, zero or extrapolate
+                       (default mirror)
+  --measure <name>     Measure of the multifractal spectrum, height or gradient (default gradient)
+  --radius <cells>     Radius of the neighbourhood tpi compares every sample with (default 8)
+  --min-prominence <h> Least prominence of the peaks listed (default 5% of the height range)
+  --interval <h>       Height between contours (default 5% of the height range)
+  --azimuth <degrees>  Direction of the sun of render clockwise from north, the top (default 315)
+  --altitude <degrees> Height of the sun of render above the horizon (default 45)
+  --lighting <name>    single, or multidirectional to light render from 4 directions (default single)
+  --z-factor <factor>  Vertical exaggeration of render (default 1)
+  --output <file>      Write to a file instead of the standard output";
+
+struct Options {
+    generator: String,
+    input: Option<String>,
+    target: Option<String>,
+    height_scale: f32,
+    n: u32,
+    seed: Option<u32>,
+    covariance: CovarianceModel,
+    points: Vec<ControlPoint>,
+    mask: Option<String>,
+    mask_target: Option<String>,
+    correlation_length: Option<f32>,
+    amplify: Option<usize>,
+    detail: DetailSource,
+    resample: Option<(usize, usize)>,
+    resample_window: ResampleWindow,
+    filter: Option<String>,
+    filter_shape: FilterShape,
+    kernel: Option<Vec<Vec<f32>>>,
+    boundary: BoundaryMode,
+    measure: Measure,
+    radius: usize,
+    min_prominence: Option<f32>,
+    interval: Option<f32>,
+    hillshade: HillshadeParams,
+    output: Option<String>,
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return Err(USAGE.to_string()),
+    };
+    if command == "help" || command == "--help" || command == "-h" {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let options = parse_options(rest)?;
+    let constraints = constraints(&options)?;
+    let filter = options.filter.as_deref().map(|value| parse_filter(value, options.filter_shape)).transpose()?;
+    let heightmap = match &options.input {
+        Some(path) => {
+            let mut heightmap = load_heightmap(path, options.height_scale)?;
+            if let Some(constraints) = &constraints {
+                check_mask(constraints, heightmap[0].len(), heightmap.len())?;
+                let correlation_length = options.correlation_length.unwrap_or(heightmap[0].len() as f32 / 8.0);
+                apply_constraints(&mut heightmap, constraints, correlation_length);
+            }
+            heightmap
+        }
+        None => {
+            let correlation_length = options.correlation_length.unwrap_or(2_usize.pow(options.n) as f32 / 8.0);
+            generate(&options.generator, options.n, options.seed, options.covariance, constraints.as_ref().map(|constraints| (constraints, correlation_length)))?
+        }
+    };
+    let heightmap = match options.amplify {
+        Some(factor) => amplify(&heightmap, factor, &AmplifyParams {
+            detail: options.detail,
+            hurst: 0.8,
+            amplitude: 1.0,
+            seed: options.seed.map_or_else(rand::random, u64::from),
+        }),
+        None => heightmap,
+    };
+    let heightmap = match options.resample {
+        Some((width, height)) => fft_resample(&heightmap, width, height, options.resample_window),
+        None => heightmap,
+    };
+    let heightmap = match &filter {
+        Some(filter) => apply_filter(&heightmap, filter),
+        None => heightmap,
+    };
+    let heightmap = match &options.kernel {
+        Some(kernel) => convolve(&heightmap, kernel, options.boundary),
+        None => heightmap,
+    };
+
+    let output = match command {
+        "multifractal" => {
+            let qs: Vec<f32> = (-10..=10).map(|k| k as f32 / 2.0).collect();
+            let spectrum = multifractal_spectrum(&heightmap, options.measure, &qs, &dyadic_scales(&heightmap, 1));
+            csv(
+                &["q", "tau", "generalized_dimension", "alpha", "f_alpha"],
+                &[&spectrum.q, &spectrum.tau, &spectrum.generalized_dimensions, &spectrum.alpha, &spectrum.f_alpha],
+            )
+        }
+        "lacunarity" => {
+            let box_sizes = dyadic_scales(&heightmap, 1);
+            let curve = lacunarity(&heightmap, &box_sizes);
+            let box_sizes: Vec<f32> = curve.box_sizes.iter().map(|&size| size as f32).collect();
+            csv(&["box_size", "lacunarity"], &[&box_sizes, &curve.lacunarity])
+        }
+        "stats" => {
+            json(&terrain_stats(&heightmap))?
+        }
+        "spectrum" => {
+            if let Some(path) = options.output.as_ref().filter(|path| path.to_lowercase().ends_with(".png")) {
+                return save_spectrum_image(&heightmap, path).map_err(|error| format!("Couldn't write {}: {}", path, error));
+            }
+            let spectrum = power_spectrum(&heightmap);
+            let beta = vec![spectrum.beta; spectrum.frequencies.len()];
+            csv(&["frequency", "power", "beta"], &[&spectrum.frequencies, &spectrum.power, &beta])
+        }
+        "correlation" => {
+            json(&correlation_lengths(&autocorrelation(&heightmap), 0.2))?
+        }
+        "compare" => {
+            let target = options.target.as_ref().ok_or("compare needs a --target")?;
+            // A target that isn't a generator is a file.
+            let target = match generate(target, options.n, options.seed, options.covariance, None) {
+                Ok(heightmap) => heightmap,
+                Err(_) => load_heightmap(target, options.height_scale)?,
+            };
+            json(&compare(&heightmap, &target))?
+        }
+        "landforms" => {
+            let path = options.output.as_ref().ok_or("landforms needs an --output PNG")?;
+            let landforms = geomorphons(&heightmap, &GeomorphonParams::default());
+            return save_landform_png(&landforms, path).map_err(|error| format!("Couldn't write {}: {}", path, error));
+        }
+        "tpi" => {
+            let tpi = topographic_position_index(&heightmap, &[options.radius]).remove(0);
+            tpi.iter().map(|row| {
+                let values: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+                values.join(",") + "\n"
+            }).collect()
+        }
+        "peaks" => {
+            let min_prominence = options.min_prominence.unwrap_or_else(|| 0.05 * height_range(&heightmap));
+            json(&find_peaks(&heightmap, min_prominence))?
+        }
+        "basins" => {
+            let mut basins = drainage_basins(&heightmap, &flow_directions(&heightmap, true)).basins;
+            basins.sort_by_key(|basin| std::cmp::Reverse(basin.area));
+            json(&basins)?
+        }
+        "contours" => {
+            let interval = options.interval.unwrap_or_else(|| 0.05 * height_range(&heightmap));
+            let lines = contours(&heightmap, interval);
+            if options.output.as_ref().is_some_and(|path| path.to_lowercase().ends_with(".svg")) {
+                contours_svg(&lines, heightmap[0].len(), heightmap.len(), 5.0 * interval)
+            } else {
+                contours_geojson(&lines, heightmap.len(), 5.0 * interval)
+            }
+        }
+        "render" => {
+            let path = options.output.as_ref().ok_or("render needs an --output PNG")?;
+            let params = ReliefParams {
+                hillshade: options.hillshade,
+                ..ReliefParams::default()
+            };
+            return save_relief_image(&heightmap, &params, path).map_err(|error| format!("Couldn't write {}: {}", path, error));
+        }
+        "preview" => {
+            let path = options.output.as_ref().ok_or("preview needs an --output PNG")?;
+            // The viewer places the camera by the size of the generator, 2^n.
+            let size = if options.input.is_some() || options.amplify.is_some() || options.resample.is_some() { heightmap[0].len() } else { 2_usize.pow(options.n) };
+            let colors: Vec<[f32; 3]> = hypsometric_tint(&heightmap, HYPSOMETRIC_RAMP).into_iter().flatten().collect();
+            let image = render_mesh(&heightmap_mesh(&heightmap), Some(&colors), &RasterParams::viewer(size));
+            return image.save(path).map_err(|error| format!("Couldn't write {}: {}", path, error));
+        }
+        _ => return Err(format!("Unknown command {}\n\n{}", command, USAGE)),
+    };
+
+    write_output(&output, &options.output)
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        generator: "fft".to_string(),
+        input: None,
+        target: None,
+        height_scale: 1.0,
+        n: 8,
+        seed: None,
+        covariance: CovarianceModel::Matern { smoothness: 1.5 },
+        points: Vec::new(),
+        mask: None,
+        mask_target: None,
+        correlation_length: None,
+        amplify: None,
+        detail: DetailSource::Spectral,
+        resample: None,
+        resample_window: ResampleWindow::Mirror,
+        filter: None,
+        filter_shape: FilterShape::Butterworth(4),
+        kernel: None,
+        boundary: BoundaryMode::Mirror,
+        measure: Measure::Gradient,
+        radius: 8,
+        min_prominence: None,
+        interval: None,
+        hillshade: HillshadeParams::default(),
+        output: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+        match flag.as_str() {
+            "--generator" => options.generator = value.clone(),
+            "--input" => options.input = Some(value.clone()),
+            "--target" => options.target = Some(value.clone()),
+            "--height-scale" => options.height_scale = value.parse().map_err(|_| format!("Invalid height scale {}", value))?,
+            "--n" => options.n = value.parse().map_err(|_| format!("Invalid exponent {}", value))?,
+            "--seed" => options.seed = Some(value.parse().map_err(|_| format!("Invalid seed {}", value))?),
+            "--covariance" => {
+                options.covariance = match value.split_once(':') {
+                    Some(("matern", smoothness)) => CovarianceModel::Matern {
+                        smoothness: smoothness.parse().ok().filter(|&smoothness: &f32| smoothness > 0.0).ok_or_else(|| format!("Invalid smoothness {}", smoothness))?,
+                    },
+                    _ => match value.as_str() {
+                        "exponential" => CovarianceModel::Exponential,
+                        "gaussian" => CovarianceModel::Gaussian,
+                        "spherical" => CovarianceModel::Spherical,
+                        "matern" => CovarianceModel::Matern { smoothness: 1.5 },
+                        _ => return Err(format!("Unknown covariance {}", value)),
+                    },
+                }
+            }
+            "--point" => options.points.push(parse_point(value)?),
+            "--mask" => options.mask = Some(value.clone()),
+            "--mask-target" => options.mask_target = Some(value.clone()),
+            "--correlation-length" => {
+                options.correlation_length = Some(
+                    value.parse().ok().filter(|&length: &f32| length.is_finite() && length > 0.0).ok_or_else(|| format!("Invalid correlation length {}", value))?,
+                )
+            }
+            "--amplify" => options.amplify = Some(value.parse().ok().filter(|&factor| factor > 0).ok_or_else(|| format!("Invalid factor {}", value))?),
+            "--detail" => {
+                options.detail = match value.as_str() {
+                    "spectral" => DetailSource::Spectral,
+                    "midpoint" => DetailSource::MidpointDisplacement,
+                    _ => return Err(format!("Unknown detail {}", value)),
+                }
+            }
+            "--resample" => options.resample = Some(parse_size(value)?),
+            "--resample-window" => {
+                options.resample_window = match value.as_str() {
+                    "none" => ResampleWindow::None,
+                    "hann" => ResampleWindow::Hann,
+                    "tukey" => ResampleWindow::Tukey(0.5),
+                    "mirror" => ResampleWindow::Mirror,
+                    _ => return Err(format!("Unknown resample window {}", value)),
+                }
+            }
+            "--filter" => options.filter = Some(value.clone()),
+            "--filter-shape" => {
+                options.filter_shape = match value.as_str() {
+                    "ideal" => FilterShape::Ideal,
+                    "gaussian" => FilterShape::Gaussian,
+                    "butterworth" => FilterShape::Butterworth(4),
+                    _ => return Err(format!("Unknown filter shape {}", value)),
+                }
+            }
+            "--kernel" => options.kernel = Some(parse_kernel(value)?),
+            "--boundary" => {
+                options.boundary = match value.as_str() {
+                    "wrap" => BoundaryMode::Wrap,
+                    "clamp" => BoundaryMode::Clamp,
+                    "mirror" => BoundaryMode::Mirror,
+                    "zero" => BoundaryMode::Zero,
+                    "extrapolate" => BoundaryMode::Extrapolate,
+                    _ => return Err(format!("Unknown boundary {}", value)),
+                }
+            }
+            "--measure" => {
+                options.measure = match value.as_str() {
+                    "height" => Measure::Height,
+                    "gradient" => Measure::Gradient,
+                    _ => return Err(format!("Unknown measure {}", value)),
+                }
+            }
+            "--radius" => options.radius = value.parse().ok().filter(|&radius| radius > 0).ok_or_else(|| format!("Invalid radius {}", value))?,
+            "--min-prominence" => options.min_prominence = Some(value.parse().map_err(|_| format!("Invalid prominence {}", value))?),
+            "--interval" => options.interval = Some(value.parse().map_err(|_| format!("Invalid interval {}", value))?),
+            "--azimuth" => options.hillshade.azimuth = value.parse().map_err(|_| format!("Invalid azimuth {}", value))?,
+            "--altitude" => options.hillshade.altitude = value.parse().map_err(|_| format!("Invalid altitude {}", value))?,
+            "--lighting" => {
+                options.hillshade.multidirectional = match value.as_str() {
+                    "single" => false,
+                    "multidirectional" => true,
+                    _ => return Err(format!("Unknown lighting {}", value)),
+                }
+            }
+            "--z-factor" => options.hillshade.z_factor = value.parse().map_err(|_| format!("Invalid z factor {}", value))?,
+            "--output" => options.output = Some(value.clone()),
+            _ => return Err(format!("Unknown option {}\n\n{}", flag, USAGE)),
+        }
+    }
+
+    Ok(options)
+}
+
+// Heightmap from one of the generators, with the same parameters as the viewer uses. The covariance is
+// the one of the field generator. Constraints come with the correlation length they blend into the
+// terrain over.
+fn generate(generator: &str, n: u32, seed: Option<u32>, covariance: CovarianceModel, constraints: Option<(&Constraints, f32)>) -> Result<Vec<Vec<f32>>, String> {
+    if let Some((constraints, _)) = constraints {
+        let size = if generator == "midpoint" || generator == "hurst" { 2_usize.pow(n) + 1 } else { 2_usize.pow(n) };
+        check_mask(constraints, size, size)?;
+    }
+
+    let noise = |noise_type: NoiseType| match (constraints, seed) {
+        (Some((constraints, correlation_length)), _) => noise_terrain_constrained(n, noise_type, seed.unwrap_or_else(rand::random), constraints, correlation_length),
+        (None, Some(seed)) => noise_terrain_par_with_seed(n, noise_type, seed),
+        (None, None) => noise_terrain_par(n, noise_type),
+    };
+    let mut heightmap = match generator {
+        "midpoint" => match constraints {
+            Some((constraints, _)) => terrain::midpoint_displacement_constrained(n, 0.75, 0.50, 45.0, constraints, seed.map_or_else(rand::random, u64::from)),
+            None => terrain::midpoint_displacement_par(n, 0.75, 0.50, 45.0, seed.map_or_else(rand::random, u64::from)),
+        },
+        "hurst" => terrain::midpoint_displacement_hurst_with_seed(n, 0.8, 20.0, true, seed.map_or_else(rand::random, u64::from)),
+        "fft" => match constraints {
+            Some((constraints, correlation_length)) => terrain::fft_terrain_constrained(n, constraints, correlation_length),
+            None => terrain::fft_terrain(n),
+        },
+        "anisotropic" => terrain::fft_terrain_anisotropic(n, &Anisotropy {
+            angle: PI / 4.0,
+            ratio: 4.0,
+            spread: Some(0.6),
+        }),
+        // Heights vary by about 10 and stay correlated over an eighth of the width.
+        "field" => {
+            let size = 2_usize.pow(n);
+            let params = CovarianceParams { model: covariance, range: size as f32 / 8.0, sill: 100.0, nugget: 0.0 };
+            gaussian_random_field(size, size, &params, seed.map_or_else(rand::random, u64::from))
+        }
+        "perlin" => noise(NoiseType::Perlin),
+        "simplex" => noise(NoiseType::Simplex),
+        "worley" => noise(NoiseType::Worley),
+        _ => return Err(format!("Unknown generator {}", generator)),
+    };
+
+    // The generators without a constrained version are constrained afterwards.
+    if let (Some((constraints, correlation_length)), "hurst" | "anisotropic" | "field") = (constraints, generator) {
+        apply_constraints(&mut heightmap, constraints, correlation_length);
+    }
+
+    Ok(heightmap)
+}
+
+// Control point from x,y,h.
+fn parse_point(value: &str) -> Result<ControlPoint, String> {
+    let invalid = || format!("Invalid point {}, expected x,y,h", value);
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    if parts.len() != 3 {
+        return Err(invalid());
+    }
+    Ok(ControlPoint {
+        x: parts[0].parse().map_err(|_| invalid())?,
+        y: parts[1].parse().map_err(|_| invalid())?,
+        height: parts[2].parse().map_err(|_| invalid())?,
+    })
+}
+
+// Size from <width>x<height>, or <size> for a square.
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("Invalid size {}, expected <width>x<height>", value);
+    let parse = |part: &str| part.trim().parse::<usize>().ok().filter(|&size| size > 0).ok_or_else(invalid);
+    match value.split_once('x') {
+        Some((width, height)) => Ok((parse(width)?, parse(height)?)),
+        None => parse(value).map(|size| (size, size)),
+    }
+}
+
+// Filter from lowpass:<cutoff>, highpass:<cutoff> or bandpass:<low>-<high>.
+fn parse_filter(value: &str, shape: FilterShape) -> Result<Filter, String> {
+    let invalid = || format!("Invalid filter {}, expected lowpass:<f>, highpass:<f> or bandpass:<low>-<high>", value);
+    let parse = |part: &str| part.trim().parse::<f32>().ok().filter(|&frequency| frequency > 0.0).ok_or_else(invalid);
+    let (band, frequencies) = value.split_once(':').ok_or_else(invalid)?;
+    match band {
+        "lowpass" => Ok(Filter::LowPass { cutoff: parse(frequencies)?, shape }),
+        "highpass" => Ok(Filter::HighPass { cutoff: parse(frequencies)?, shape }),
+        "bandpass" => {
+            let (low, high) = frequencies.split_once('-').ok_or_else(invalid)?;
+            let (low, high) = (parse(low)?, parse(high)?);
+            if low >= high {
+                return Err(invalid());
+            }
+            Ok(Filter::BandPass { low, high, shape })
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// Kernel from gaussian:<sigma>, box:<radius>, unsharp:<sigma>,<amount> or laplacian.
+fn parse_kernel(value: &str) -> Result<Vec<Vec<f32>>, String> {
+    let invalid = || format!("Invalid kernel {}, expected gaussian:<sigma>, box:<radius>, unsharp:<sigma>,<amount> or laplacian", value);
+    let (name, parameters) = value.split_once(':').unwrap_or((value, ""));
+    let parameters: Vec<f32> = parameters.split(',').filter(|part| !part.trim().is_empty())
+        .map(|part| part.trim().parse::<f32>().ok().filter(|&parameter| parameter > 0.0).ok_or_else(invalid))
+        .collect::<Result<_, _>>()?;
+    match (name, parameters.as_slice()) {
+        ("gaussian", &[sigma]) => Ok(gaussian_kernel(sigma)),
+        ("box", &[radius]) if radius.fract() == 0.0 => Ok(box_kernel(radius as usize)),
+        ("unsharp", &[sigma, amount]) => Ok(unsharp_mask_kernel(sigma, amount)),
+        ("laplacian", &[]) => Ok(laplacian_kernel()),
+        _ => Err(invalid()),
+    }
+}
+
+// Constraints from the --point or --mask options, if there are any.
+fn constraints(options: &Options) -> Result<Option<Constraints>, String> {
+    match (&options.mask, &options.mask_target) {
+        (None, None) if options.points.is_empty() => Ok(None),
+        (None, None) => Ok(Some(Constraints::Points(options.points.clone()))),
+        (Some(mask), Some(target)) if options.points.is_empty() => Ok(Some(Constraints::Mask {
+            mask: load_heightmap(mask, 1.0)?,
+            target: load_heightmap(target, options.height_scale)?,
+        })),
+        (Some(_), Some(_)) => Err("Constrain the heightmap by --point or by --mask, not both".to_string()),
+        _ => Err("--mask and --mask-target go together".to_string()),
+    }
+}
+
+// Masks have to be the size of the heightmap they constrain.
+fn check_mask(constraints: &Constraints, width: usize, height: usize) -> Result<(), String> {
+    if let Constraints::Mask { mask, target } = constraints {
+        if [mask, target].iter().any(|map| map.len() != height || map[0].len() != width) {
+            return Err(format!("--mask and --mask-target have to be {} x {} like the heightmap", width, height));
+        }
+    }
+    Ok(())
+}
+
+// Highest minus lowest height.
+fn height_range(heightmap: &[Vec<f32>]) -> f32 {
+    let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+    let highest = heightmap.iter().flatten().fold(f32::NEG_INFINITY, |highest, &value| highest.max(value));
+    highest - lowest
+}
+
+// CSV with a header row and one row per entry of the columns.
+fn csv(header: &[&str], columns: &[&Vec<f32>]) -> String {
+    let mut output = header.join(",");
+    output.push('\n');
+
+    let rows = columns.iter().map(|column| column.len()).min().unwrap_or(0);
+    for row in 0..rows {
+        let values: Vec<String> = columns.iter().map(|column| column[row].to_string()).collect();
+        output.push_str(&values.join(","));
+        output.push('\n');
+    }
+
+    output
+}
+
+fn json(value: &impl Serialize) -> Result<String, String> {
+    let mut json = serde_json::to_string_pretty(value).map_err(|error| error.to_string())?;
+    json.push('\n');
+    Ok(json)
+}
+
+fn write_output(output: &str, path: &Option<String>) -> Result<(), String> {
+    match path {
+        Some(path) => fs::write(path, output).map_err(|error| format!("Couldn't write {}: {}", path, error)),
+        None => {
+            print!("{}", output);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::convolution::{convolve, gaussian_kernel, BoundaryMode};
+use crate::derivatives::{surface_derivatives, DerivativeParams, SlopeUnit};
+use crate::fractal_analysis::{calculate_fractal_dimension, power_spectrum};
+use crate::resample::{fft_resample, ResampleWindow};
+use serde::Serialize;
+
+// How close a heightmap is to a target, e.g. a generated terrain to a real DEM, for tuning generator
+// parameters. Each metric is 0.0 for identical heightmaps, except SSIM which is 1.0.
+
+#[derive(Serialize)]
+pub struct ComparisonReport {
+    // Root mean square difference of the heights, in the units of the heightmaps.
+    pub rmse: f32,
+    // Mean structural similarity of the heights, each normalized to their own range, from -1.0 to 1.0.
+    // Compares local means, contrasts and patterns rather than absolute heights.
+    pub ssim: f32,
+    // Wasserstein (earth mover's) distance between the slope distributions, in degrees. How far the
+    // slopes of one heightmap have to move on average to match the other, wherever they are.
+    pub slope_wasserstein: f32,
+    // Root mean square difference of the log10 radially averaged power spectra over the frequencies both
+    // have, in decades. Compares how the roughness is spread over the scales.
+    pub psd_distance: f32,
+    // Fractal dimension of the heightmap minus that of the target.
+    pub fractal_dimension_difference: f32,
+}
+
+// Standard deviation of the gaussian window of SSIM, in samples.
+const SSIM_SIGMA: f32 = 1.5;
+// Stabilizing constants of SSIM for heights normalized to [0, 1].
+const SSIM_C1: f32 = 0.01 * 0.01;
+const SSIM_C2: f32 = 0.03 * 0.03;
+
+// Compares a heightmap with a target of any size. When the sizes differ, the target is resampled to the
+// size of the heightmap for the metrics that compare sample by sample, RMSE and SSIM. The others compare
+// distributions and are measured on each heightmap as it is.
+pub fn compare(heightmap: &[Vec<f32>], target: &[Vec<f32>]) -> ComparisonReport {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let resampled;
+    let matching_target = if target.len() == height && target[0].len() == width {
+        target
+    } else {
+        resampled = fft_resample(target, width, height, ResampleWindow::Mirror);
+        &resampled
+    };
+
+    ComparisonReport {
+        rmse: rmse(heightmap, matching_target),
+        ssim: ssim(heightmap, matching_target),
+        slope_wasserstein: wasserstein_distance(slopes(heightmap), slopes(target)),
+        psd_distance: psd_distance(heightmap, target),
+        fractal_dimension_difference: calculate_fractal_dimension(heightmap) - calculate_fractal_dimension(target),
+    }
+}
+
+pub fn rmse(a: &[Vec<f32>], b: &[Vec<f32>]) -> f32 {
+    let (sum, count) = a.iter().flatten().zip(b.iter().flatten())
+        .fold((0.0_f64, 0_usize), |(sum, count), (&a, &b)| (sum + ((a - b) as f64).powi(2), count + 1));
+    (sum / count.max(1) as f64).sqrt() as f32
+}
+
+// Mean structural similarity (Wang et al.) with an 11 x 11 gaussian window, of two heightmaps of the same
+// size, each normalized to [0, 1] first.
+pub fn ssim(a: &[Vec<f32>], b: &[Vec<f32>]) -> f32 {
+    let a = normalized(a);
+    let b = normalized(b);
+    let product = |x: &[Vec<f32>], y: &[Vec<f32>]| -> Vec<Vec<f32>> {
+        x.iter().zip(y.iter()).map(|(row_x, row_y)| row_x.iter().zip(row_y.iter()).map(|(x, y)| x * y).collect()).collect()
+    };
+
+    let kernel = gaussian_kernel(SSIM_SIGMA);
+    let local_mean = |map: &[Vec<f32>]| convolve(map, &kernel, BoundaryMode::Mirror);
+    let mean_a = local_mean(&a);
+    let mean_b = local_mean(&b);
+    let mean_aa = local_mean(&product(&a, &a));
+    let mean_bb = local_mean(&product(&b, &b));
+    let mean_ab = local_mean(&product(&a, &b));
+
+    let mut sum = 0.0_f64;
+    let mut count = 0;
+    for i in 0..a.len() {
+        for j in 0..a[0].len() {
+            let (mu_a, mu_b) = (mean_a[i][j], mean_b[i][j]);
+            let variance_a = mean_aa[i][j] - mu_a * mu_a;
+            let variance_b = mean_bb[i][j] - mu_b * mu_b;
+            let covariance = mean_ab[i][j] - mu_a * mu_b;
+
+            let similarity = ((2.0 * mu_a * mu_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mu_a * mu_a + mu_b * mu_b + SSIM_C1) * (variance_a + variance_b + SSIM_C2));
+            sum += similarity as f64;
+            count += 1;
+        }
+    }
+
+    (sum / count as f64) as f32
+}
+
+// First Wasserstein distance between two samples of any sizes, the integral of the absolute difference
+// between their quantile functions.
+pub fn wasserstein_distance(mut a: Vec<f32>, mut b: Vec<f32>) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    a.sort_by(|x, y| x.total_cmp(y));
+    b.sort_by(|x, y| x.total_cmp(y));
+
+    // Both quantile functions are steps, a[i] up to (i + 1) / a.len() and b[j] up to (j + 1) / b.len().
+    // Walking the breakpoints of both in order, they're constant in between, so the integral is exact.
+    // Positions are counted in units of 1 / (a.len() * b.len()) to keep them whole numbers.
+    let (mut i, mut j, mut position) = (0, 0, 0);
+    let mut sum = 0.0_f64;
+    while i < a.len() && j < b.len() {
+        let (end_a, end_b) = ((i + 1) * b.len(), (j + 1) * a.len());
+        let end = end_a.min(end_b);
+        sum += (a[i] - b[j]).abs() as f64 * (end - position) as f64;
+        position = end;
+        if end_a == end {
+            i += 1;
+        }
+        if end_b == end {
+            j += 1;
+        }
+    }
+
+    (sum / (a.len() * b.len()) as f64) as f32
+}
+
+// Root mean square difference between the log10 radially averaged power spectra. Frequencies are in
+// cycles per sample, so heightmaps of different sizes are compared over the frequencies both resolve,
+// with the spectrum of the target interpolated linearly in log-log space.
+pub fn psd_distance(a: &[Vec<f32>], b: &[Vec<f32>]) -> f32 {
+    let spectrum_a = power_spectrum(a);
+    let spectrum_b = power_spectrum(b);
+    let log_frequencies_b: Vec<f32> = spectrum_b.frequencies.iter().map(|frequency| frequency.log10()).collect();
+    let log_power_b: Vec<f32> = spectrum_b.power.iter().map(|power| power.log10()).collect();
+
+    let mut sum = 0.0_f64;
+    let mut count = 0;
+    for (&frequency, &power) in spectrum_a.frequencies.iter().zip(spectrum_a.power.iter()) {
+        let log_frequency = frequency.log10();
+        // First bin of the target at or above the frequency.
+        let k = log_frequencies_b.partition_point(|&value| value < log_frequency);
+        let interpolated = if k < log_frequencies_b.len() && log_frequencies_b[k] == log_frequency {
+            log_power_b[k]
+        } else if k == 0 || k == log_frequencies_b.len() {
+            continue;
+        } else {
+            let t = (log_frequency - log_frequencies_b[k - 1]) / (log_frequencies_b[k] - log_frequencies_b[k - 1]);
+            log_power_b[k - 1] * (1.0 - t) + log_power_b[k] * t
+        };
+
+        sum += ((power.log10() - interpolated) as f64).powi(2);
+        count += 1;
+    }
+
+    if count == 0 {
+        0.0
+    } else {
+        (sum / count as f64).sqrt() as f32
+    }
+}
+
+// Slope of every sample in degrees.
+fn slopes(heightmap: &[Vec<f32>]) -> Vec<f32> {
+    surface_derivatives(heightmap, &DerivativeParams::default()).slope(SlopeUnit::Degrees).into_iter().flatten().collect()
+}
+
+// Heightmap scaled to [0, 1].
+fn normalized(heightmap: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+    let highest = heightmap.iter().flatten().fold(f32::NEG_INFINITY, |highest, &value| highest.max(value));
+    let range = highest - lowest;
+    heightmap.iter().map(|row|
+        row.iter().map(|&value| if range > 0.0 { (value - lowest) / range } else { 0.0 }).collect()
+    ).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::hills;
+    use crate::random_fields::{gaussian_random_field, CovarianceModel, CovarianceParams};
+
+    fn field(size: usize, model: CovarianceModel, seed: u64) -> Vec<Vec<f32>> {
+        gaussian_random_field(size, size, &CovarianceParams { model, range: 6.0, sill: 1.0, nugget: 0.0 }, seed)
+    }
+
+    #[test]
+    fn a_heightmap_matches_itself() {
+        let heightmap = hills(32);
+        assert_eq!(rmse(&heightmap, &heightmap), 0.0);
+        assert!((ssim(&heightmap, &heightmap) - 1.0).abs() < 1e-4);
+
+        let report = compare(&heightmap, &heightmap);
+        assert_eq!((report.rmse, report.slope_wasserstein, report.psd_distance, report.fractal_dimension_difference), (0.0, 0.0, 0.0, 0.0));
+        assert!((report.ssim - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn wasserstein_distance_of_a_shifted_sample_is_the_shift() {
+        let a: Vec<f32> = (0..50).map(|k| (k as f32 * 0.37).sin() * 10.0).collect();
+        let b: Vec<f32> = a.iter().rev().map(|value| value + 2.5).collect();
+        assert!((wasserstein_distance(a.clone(), b.clone()) - 2.5).abs() < 1e-4);
+        assert!((wasserstein_distance(b, a) - 2.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn wasserstein_distance_integrates_samples_of_different_sizes_exactly() {
+        // The quantile functions differ by 0 up to 1/3, 1 up to 1/2, 2 up to 2/3 and 1 up to 1.
+        assert!((wasserstein_distance(vec![2.0, 0.0, 1.0], vec![3.0, 0.0]) - 5.0 / 6.0).abs() < 1e-6);
+        // A sample repeated is the same distribution.
+        assert!(wasserstein_distance(vec![1.0, 4.0], vec![4.0, 1.0, 1.0, 4.0]).abs() < 1e-6);
+        assert_eq!(wasserstein_distance(Vec::new(), vec![1.0]), 0.0);
+    }
+
+    #[test]
+    fn power_spectra_of_one_generator_match_at_any_size() {
+        let small = field(64, CovarianceModel::Exponential, 1);
+        let large = field(128, CovarianceModel::Exponential, 2);
+        let smooth = field(128, CovarianceModel::Gaussian, 2);
+
+        let same = psd_distance(&small, &large);
+        let different = psd_distance(&small, &smooth);
+        assert!(same < 0.25, "same generator {}", same);
+        assert!(different > 4.0 * same, "different generators {} against {}", different, same);
+    }
+}
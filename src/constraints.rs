@@ -0,0 +1,203 @@
+use crate::convolution::{convolve, gaussian_kernel, BoundaryMode};
+
+// Constraints that a generated heightmap has to honour, e.g. a mountain at a specific spot or a flat
+// plateau for a town.
+
+// Fixed height at column x and row y of the heightmap.
+#[derive(Clone, Copy)]
+pub struct ControlPoint {
+    pub x: usize,
+    pub y: usize,
+    pub height: f32,
+}
+
+pub enum Constraints {
+    // Sparse control points. Points outside of the heightmap are ignored.
+    Points(Vec<ControlPoint>),
+    // Where the mask is 1 the heightmap has to match the target, where it is 0 it is left free. Values
+    // in between blend the two. Both have to be the size of the heightmap.
+    Mask {
+        mask: Vec<Vec<f32>>,
+        target: Vec<Vec<f32>>,
+    },
+}
+
+impl Constraints {
+    // Heights of the cells that are fixed by the constraints, for generators that build the heightmap
+    // cell by cell. Mask cells count as fixed from 0.5 onwards.
+    pub fn fixed_nodes(&self, width: usize, height: usize) -> Vec<Vec<Option<f32>>> {
+        let mut fixed = vec![vec![None; width]; height];
+
+        match self {
+            Constraints::Points(points) => {
+                for point in points.iter().filter(|point| point.x < width && point.y < height) {
+                    fixed[point.y][point.x] = Some(point.height);
+                }
+            }
+            Constraints::Mask { mask, target } => {
+                for i in 0..height {
+                    for j in 0..width {
+                        if mask[i][j] >= 0.5 {
+                            fixed[i][j] = Some(target[i][j]);
+                        }
+                    }
+                }
+            }
+        }
+
+        fixed
+    }
+}
+
+// Makes an already generated heightmap honour the constraints while keeping its detail elsewhere. The
+// correction is a smooth residual field that fades out over about correlation_length cells, like the
+// conditioning step of a conditional simulation.
+//
+// - Points: the residuals (target - generated) at the control points are interpolated with simple
+//   kriging, which is exact at the points and goes back to zero away from them.
+// - Mask: masked cells are blended towards the target, and the residual around the masked area is
+//   spread outwards with a normalized gaussian blur so the surrounding terrain rises or sinks to meet
+//   it instead of ending in a cliff.
+pub fn apply_constraints(heightmap: &mut [Vec<f32>], constraints: &Constraints, correlation_length: f32) {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+
+    match constraints {
+        Constraints::Points(points) => {
+            let points: Vec<ControlPoint> = points.iter().copied().filter(|point| point.x < width && point.y < height).collect();
+            if points.is_empty() {
+                return;
+            }
+
+            let residuals: Vec<f32> = points.iter().map(|point| point.height - heightmap[point.y][point.x]).collect();
+            let weights = kriging_weights(&points, &residuals, correlation_length);
+
+            for (i, row) in heightmap.iter_mut().enumerate() {
+                for (j, height) in row.iter_mut().enumerate() {
+                    let correction: f32 = points.iter().zip(weights.iter()).map(|(point, weight)| {
+                        weight * covariance(distance(j, i, point.x, point.y), correlation_length)
+                    }).sum();
+                    *height += correction;
+                }
+            }
+        }
+        Constraints::Mask { mask, target } => {
+            let mut weighted_residual = vec![vec![0.0; width]; height];
+            for i in 0..height {
+                for j in 0..width {
+                    weighted_residual[i][j] = mask[i][j] * (target[i][j] - heightmap[i][j]);
+                }
+            }
+
+            let kernel = gaussian_kernel(correlation_length);
+            let spread_residual = convolve(&weighted_residual, &kernel, BoundaryMode::Clamp);
+            let spread_mask = convolve(mask, &kernel, BoundaryMode::Clamp);
+
+            for i in 0..height {
+                for j in 0..width {
+                    // Normalized convolution: the average residual of the masked cells nearby, faded out
+                    // by how much of the neighbourhood is masked.
+                    let residual = if spread_mask[i][j] > 1e-6 { spread_residual[i][j] / spread_mask[i][j] } else { 0.0 };
+                    let fade = spread_mask[i][j].min(1.0);
+                    let free = heightmap[i][j] + residual * fade;
+
+                    heightmap[i][j] = mask[i][j] * target[i][j] + (1.0 - mask[i][j]) * free;
+                }
+            }
+        }
+    }
+}
+
+fn distance(x0: usize, y0: usize, x1: usize, y1: usize) -> f32 {
+    ((x0 as f32 - x1 as f32).powi(2) + (y0 as f32 - y1 as f32).powi(2)).sqrt()
+}
+
+// Matérn covariance with smoothness 3/2. It is smooth enough not to leave cones at the control points
+// and, unlike a gaussian covariance, keeps the kriging system well conditioned for close points.
+fn covariance(distance: f32, correlation_length: f32) -> f32 {
+    let scaled = 3.0_f32.sqrt() * distance / correlation_length;
+    (1.0 + scaled) * (-scaled).exp()
+}
+
+// Solves the simple kriging system C w = r, so that the sum of w_k * covariance(d_k) is exactly r at
+// every control point.
+fn kriging_weights(points: &[ControlPoint], residuals: &[f32], correlation_length: f32) -> Vec<f32> {
+    let n = points.len();
+
+    // Augmented matrix [C | r], with a tiny nugget on the diagonal in case two points coincide.
+    let mut system: Vec<Vec<f64>> = points.iter().enumerate().map(|(a, point_a)| {
+        let mut row: Vec<f64> = points.iter().map(|point_b| {
+            covariance(distance(point_a.x, point_a.y, point_b.x, point_b.y), correlation_length) as f64
+        }).collect();
+        row[a] += 1e-6;
+        row.push(residuals[a] as f64);
+        row
+    }).collect();
+
+    // Gaussian elimination with partial pivoting.
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs()))
+            .unwrap();
+        system.swap(column, pivot);
+
+        let (upper, lower) = system.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for row in lower.iter_mut() {
+            let factor = row[column] / pivot_row[column];
+            for (value, pivot_value) in row[column..].iter_mut().zip(pivot_row[column..].iter()) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+
+    let mut weights = vec![0.0_f64; n];
+    for row in (0..n).rev() {
+        let sum: f64 = ((row + 1)..n).map(|k| system[row][k] * weights[k]).sum();
+        weights[row] = (system[row][n] - sum) / system[row][row];
+    }
+
+    weights.into_iter().map(|weight| weight as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::hills;
+
+    #[test]
+    fn control_points_are_honoured() {
+        let points = vec![
+            ControlPoint { x: 10, y: 20, height: 100.0 },
+            ControlPoint { x: 40, y: 12, height: -30.0 },
+            ControlPoint { x: 44, y: 14, height: 60.0 },
+        ];
+
+        let mut heightmap = hills(64);
+        apply_constraints(&mut heightmap, &Constraints::Points(points.clone()), 8.0);
+        for point in &points {
+            assert!((heightmap[point.y][point.x] - point.height).abs() < 0.01);
+        }
+        // Far from the points the terrain is left almost as it was.
+        assert!((heightmap[60][2] - hills(64)[60][2]).abs() < 1.0);
+    }
+
+    #[test]
+    fn masked_cells_match_the_target() {
+        let mask: Vec<Vec<f32>> = (0..32).map(|i| (0..32).map(|j| if (8..16).contains(&i) && (8..16).contains(&j) { 1.0 } else { 0.0 }).collect()).collect();
+        let constraints = Constraints::Mask {
+            mask: mask.clone(),
+            target: vec![vec![20.0; 32]; 32],
+        };
+
+        let mut heightmap = hills(32);
+        apply_constraints(&mut heightmap, &constraints, 4.0);
+        for (mask_row, row) in mask.iter().zip(heightmap.iter()) {
+            for (&masked, &height) in mask_row.iter().zip(row.iter()) {
+                if masked == 1.0 {
+                    assert_eq!(height, 20.0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+// Contour lines of a heightmap with marching squares, for printing topographic maps.
+//
+// Points are (x, y) with x the column and y the row of the heightmap, so the first row is at the top like
+// in an image. Each cell between 4 samples is crossed by a contour wherever its corners are on different
+// sides of the level, and the crossings are placed on the cell edges by linear interpolation. Samples
+// exactly at the level count as above it.
+
+#[derive(Clone, Debug)]
+pub struct Contour {
+    pub level: f32,
+    // Walking along the points, higher ground is on the left with the first row at the top.
+    pub points: Vec<[f32; 2]>,
+    // Whether the last point connects back to the first one. Open contours run from edge to edge of the
+    // heightmap.
+    pub closed: bool,
+}
+
+// Contours at every multiple of the interval from the lowest sample up to, but not at, the highest.
+pub fn contours(heightmap: &[Vec<f32>], interval: f32) -> Vec<Contour> {
+    let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+    let highest = heightmap.iter().flatten().fold(f32::NEG_INFINITY, |highest, &value| highest.max(value));
+    if interval <= 0.0 || heightmap.len() < 2 || heightmap[0].len() < 2 {
+        return Vec::new();
+    }
+
+    let mut contours = Vec::new();
+    let mut k = (lowest / interval).ceil() as i64;
+    while (k as f32 * interval) < highest {
+        contours.extend(contours_at(heightmap, k as f32 * interval));
+        k += 1;
+    }
+    contours
+}
+
+// Contours at a single level.
+fn contours_at(heightmap: &[Vec<f32>], level: f32) -> Vec<Contour> {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let above = |i: usize, j: usize| heightmap[i][j] >= level;
+
+    // Crossings are identified by the edge they're on. Horizontal edges from (i, j) to (i, j + 1) come
+    // first, then vertical ones from (i, j) to (i + 1, j).
+    let horizontal = |i: usize, j: usize| i * width + j;
+    let vertical = |i: usize, j: usize| height * width + i * width + j;
+
+    // Segments from crossing to crossing, directed so higher ground is on the left.
+    let mut next: HashMap<usize, usize> = HashMap::new();
+    for i in 0..height - 1 {
+        for j in 0..width - 1 {
+            // Corners and edges clockwise from the top left, edge k runs from corner k to corner k + 1.
+            let corners = [above(i, j), above(i, j + 1), above(i + 1, j + 1), above(i + 1, j)];
+            let edges = [horizontal(i, j), vertical(i, j + 1), horizontal(i + 1, j), vertical(i, j)];
+            // Going clockwise, an edge rises when it runs from a corner below the level to one above it.
+            // Segments run from a rising edge to a falling one, which keeps higher ground on the left.
+            let rising: Vec<usize> = (0..4).filter(|&k| !corners[k] && corners[(k + 1) % 4]).collect();
+            match rising.len() {
+                0 => continue,
+                1 => {
+                    let k = rising[0];
+                    let falling = (0..4).find(|&k| corners[k] && !corners[(k + 1) % 4]).unwrap();
+                    next.insert(edges[k], edges[falling]);
+                }
+                _ => {
+                    // Saddle: when the mean of the corners is above the level, the corners above are
+                    // connected through the middle of the cell and the segments cut off the ones below,
+                    // otherwise they cut off the ones above.
+                    let center = (heightmap[i][j] + heightmap[i][j + 1] + heightmap[i + 1][j + 1] + heightmap[i + 1][j]) / 4.0 >= level;
+                    for &k in &rising {
+                        let falling = if center { (k + 3) % 4 } else { (k + 1) % 4 };
+                        next.insert(edges[k], edges[falling]);
+                    }
+                }
+            }
+        }
+    }
+
+    // Chain the segments. Open contours start at a crossing nothing leads to, on the edge of the
+    // heightmap, and whatever is left over is closed.
+    let targets: HashSet<usize> = next.values().copied().collect();
+    let mut starts: Vec<usize> = next.keys().copied().filter(|crossing| !targets.contains(crossing)).collect();
+    starts.sort_unstable();
+    let mut remaining: Vec<usize> = next.keys().copied().collect();
+    remaining.sort_unstable();
+
+    let point = |crossing: usize| -> [f32; 2] {
+        let (i, j, di, dj) = if crossing < height * width {
+            (crossing / width, crossing % width, 0, 1)
+        } else {
+            let crossing = crossing - height * width;
+            (crossing / width, crossing % width, 1, 0)
+        };
+        let (a, b) = (heightmap[i][j], heightmap[i + di][j + dj]);
+        let t = if a == b { 0.5 } else { ((level - a) / (b - a)).clamp(0.0, 1.0) };
+        [j as f32 + t * dj as f32, i as f32 + t * di as f32]
+    };
+
+    let mut contours = Vec::new();
+    let mut visited = HashSet::new();
+    for (start, closed) in starts.into_iter().map(|start| (start, false)).chain(remaining.into_iter().map(|start| (start, true))) {
+        if visited.contains(&start) {
+            continue;
+        }
+        let mut points = vec![point(start)];
+        visited.insert(start);
+        let mut crossing = start;
+        while let Some(&to) = next.get(&crossing) {
+            if to == start {
+                break;
+            }
+            points.push(point(to));
+            visited.insert(to);
+            crossing = to;
+        }
+        contours.push(Contour {
+            level,
+            points,
+            closed,
+        });
+    }
+
+    contours
+}
+
+// Whether a level is a multiple of the index interval. Index contours are drawn heavier and labelled.
+fn is_index(level: f32, index_interval: f32) -> bool {
+    index_interval > 0.0 && ((level / index_interval).round() * index_interval - level).abs() <= 1e-3 * index_interval
+}
+
+// SVG drawing of the contours of a width x height heightmap, one unit per sample. Contours at multiples
+// of index_interval are index contours, drawn heavier and labelled with their level.
+pub fn contours_svg(contours: &[Contour], width: usize, height: usize, index_interval: f32) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}">"#, width - 1, height - 1);
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    let _ = writeln!(svg, r#"<g fill="none" stroke="saddlebrown" stroke-linejoin="round" stroke-linecap="round">"#);
+
+    for contour in contours {
+        let mut path = String::new();
+        for (k, [x, y]) in contour.points.iter().enumerate() {
+            let _ = write!(path, "{}{:.2} {:.2} ", if k == 0 { "M" } else { "L" }, x, y);
+        }
+        if contour.closed {
+            path.push('Z');
+        }
+        let stroke_width = if is_index(contour.level, index_interval) { 0.6 } else { 0.25 };
+        let _ = writeln!(svg, r#"<path d="{}" stroke-width="{}"/>"#, path.trim_end(), stroke_width);
+    }
+    let _ = writeln!(svg, "</g>");
+
+    // Labels in the middle of the index contours.
+    let _ = writeln!(svg, r#"<g font-family="sans-serif" font-size="3" fill="saddlebrown" text-anchor="middle">"#);
+    for contour in contours.iter().filter(|contour| is_index(contour.level, index_interval)) {
+        let [x, y] = contour.points[contour.points.len() / 2];
+        // Rounded so levels like 3 * 0.1 don't come out as 0.30000001.
+        let _ = writeln!(svg, r#"<text x="{:.2}" y="{:.2}">{}</text>"#, x, y, (contour.level * 1000.0).round() / 1000.0);
+    }
+    let _ = writeln!(svg, "</g>");
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// GeoJSON FeatureCollection of the contours of a heightmap with the given number of rows, one
+// LineString feature per contour with its level, whether it's closed and whether it's an index contour.
+// Coordinates are [column, rows - 1 - row], so north, the first row, is up.
+pub fn contours_geojson(contours: &[Contour], height: usize, index_interval: f32) -> String {
+    let features: Vec<serde_json::Value> = contours.iter().map(|contour| {
+        let mut coordinates: Vec<[f32; 2]> = contour.points.iter().map(|&[x, y]| [x, (height - 1) as f32 - y]).collect();
+        if contour.closed {
+            coordinates.push(coordinates[0]);
+        }
+        serde_json::json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "LineString",
+                "coordinates": coordinates,
+            },
+            "properties": {
+                "level": contour.level,
+                "closed": contour.closed,
+                "index": is_index(contour.level, index_interval),
+            },
+        })
+    }).collect();
+
+    serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features,
+    }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cone 10.5 high in the middle of a 21 x 21 heightmap, falling by 1 per sample.
+    fn cone() -> Vec<Vec<f32>> {
+        (0..21).map(|i| (0..21).map(|j| 10.5 - ((i as f32 - 10.0).powi(2) + (j as f32 - 10.0).powi(2)).sqrt()).collect()).collect()
+    }
+
+    // Twice the signed area of a ring, negative when it turns counterclockwise on screen, with y down.
+    fn signed_area(points: &[[f32; 2]]) -> f32 {
+        (0..points.len()).map(|k| {
+            let ([x0, y0], [x1, y1]) = (points[k], points[(k + 1) % points.len()]);
+            x0 * y1 - x1 * y0
+        }).sum()
+    }
+
+    // Both ends of every contour of a single cell, rounded to avoid float noise.
+    fn segments(heightmap: &[Vec<f32>], level: f32) -> Vec<[[i32; 2]; 2]> {
+        let round = |[x, y]: [f32; 2]| [(x * 10.0).round() as i32, (y * 10.0).round() as i32];
+        let mut segments: Vec<[[i32; 2]; 2]> = contours_at(heightmap, level).iter().map(|contour| {
+            assert!(!contour.closed);
+            assert_eq!(contour.points.len(), 2);
+            [round(contour.points[0]), round(contour.points[1])]
+        }).collect();
+        segments.sort();
+        segments
+    }
+
+    #[test]
+    fn saddles_connect_the_corners_on_the_side_of_the_cell_mean() {
+        // Corners above the level on the main diagonal. Lines are in tenths of a sample, and each runs with
+        // higher ground on its left.
+        let main = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+        // Below the mean of 0.5 the corners above are joined through the middle and the ones below cut off.
+        assert_eq!(segments(&main, 0.4), [[[0, 6], [4, 10]], [[10, 4], [6, 0]]]);
+        // Above the mean it's the other way round.
+        assert_eq!(segments(&main, 0.6), [[[0, 4], [4, 0]], [[10, 6], [6, 10]]]);
+
+        // And on the other diagonal.
+        let anti = vec![vec![0.0, 1.0], vec![1.0, 0.0]];
+        assert_eq!(segments(&anti, 0.4), [[[4, 0], [0, 4]], [[6, 10], [10, 6]]]);
+        assert_eq!(segments(&anti, 0.6), [[[4, 10], [0, 6]], [[6, 0], [10, 4]]]);
+    }
+
+    #[test]
+    fn a_cone_has_closed_rings_inside_and_open_lines_across_the_corners() {
+        let contours = contours(&cone(), 2.0);
+
+        for level in [2.0, 4.0, 6.0, 8.0, 10.0] {
+            let rings: Vec<&Contour> = contours.iter().filter(|contour| contour.level == level).collect();
+            assert_eq!(rings.len(), 1, "level {}", level);
+            assert!(rings[0].closed);
+            for &[x, y] in &rings[0].points {
+                let distance = ((x - 10.0).powi(2) + (y - 10.0).powi(2)).sqrt();
+                assert!((distance - (10.5 - level)).abs() < 0.1, "level {} at {:?}", level, [x, y]);
+            }
+            // Higher ground, inside the ring, is on the left.
+            assert!(signed_area(&rings[0].points) < 0.0);
+        }
+
+        // Rings 10.5 and 12.5 from the middle only fit on the heightmap in its corners.
+        for level in [0.0, -2.0] {
+            let lines: Vec<&Contour> = contours.iter().filter(|contour| contour.level == level).collect();
+            assert_eq!(lines.len(), 4, "level {}", level);
+            for line in lines {
+                assert!(!line.closed);
+                for point in [line.points[0], line.points[line.points.len() - 1]] {
+                    assert!(point.iter().any(|&coordinate| coordinate == 0.0 || coordinate == 20.0), "{:?}", point);
+                }
+            }
+        }
+        assert_eq!(contours.len(), 5 + 2 * 4);
+    }
+
+    #[test]
+    fn index_contours_are_heavier_and_labelled() {
+        let contours = contours(&cone(), 2.0);
+        let is_index_level = |level: f32| level.rem_euclid(4.0) == 0.0;
+        let index_contours = contours.iter().filter(|contour| is_index_level(contour.level)).count();
+        assert_eq!(index_contours, 2 + 4);
+
+        let svg = contours_svg(&contours, 21, 21, 4.0);
+        assert_eq!(svg.matches(r#"stroke-width="0.6""#).count(), index_contours);
+        assert_eq!(svg.matches(r#"stroke-width="0.25""#).count(), contours.len() - index_contours);
+        assert_eq!(svg.matches("<text").count(), index_contours);
+        assert!(svg.contains(">8</text>"));
+
+        let geojson: serde_json::Value = serde_json::from_str(&contours_geojson(&contours, 21, 4.0)).unwrap();
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), contours.len());
+        for (feature, contour) in features.iter().zip(&contours) {
+            assert_eq!(feature["properties"]["index"], is_index_level(contour.level));
+            let coordinates = feature["geometry"]["coordinates"].as_array().unwrap();
+            // Rows count up from the bottom, and closed contours repeat their first point.
+            assert_eq!(coordinates[0][1].as_f64().unwrap() as f32, 20.0 - contour.points[0][1]);
+            assert_eq!(coordinates.len(), contour.points.len() + contour.closed as usize);
+            if contour.closed {
+                assert_eq!(coordinates[0], coordinates[coordinates.len() - 1]);
+            }
+        }
+    }
+}
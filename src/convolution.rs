@@ -0,0 +1,232 @@
+use crate::fft_utils::{apply_fft_to_rect_grid, apply_ifft_to_rect_grid};
+use rustfft::num_complex::Complex;
+
+// How samples outside of the heightmap are read.
+#[derive(Clone, Copy)]
+pub enum BoundaryMode {
+    // The heightmap repeats, for heightmaps that tile.
+    Wrap,
+    // The edge sample repeats.
+    Clamp,
+    // The heightmap is reflected at its edges, the edge sample isn't repeated.
+    Mirror,
+    // Everything outside is 0.
+    Zero,
+    // The heightmap continues in a straight line past its edges, so slopes at the edge carry on.
+    Extrapolate,
+}
+
+// Kernels with at most this many weights are applied directly, larger ones through the FFT.
+const DIRECT_CONVOLUTION_LIMIT: usize = 81;
+
+// Convolves a heightmap with a kernel. The kernel needs an odd width and height, its center weight is
+// applied to the sample being computed. Small kernels are applied directly, large kernels through the
+// FFT, both give the same result.
+pub fn convolve(heightmap: &[Vec<f32>], kernel: &[Vec<f32>], boundary_mode: BoundaryMode) -> Vec<Vec<f32>> {
+    assert!(kernel.len() % 2 == 1 && kernel[0].len() % 2 == 1, "kernels need an odd width and height");
+
+    if kernel.len() * kernel[0].len() <= DIRECT_CONVOLUTION_LIMIT {
+        convolve_direct(heightmap, kernel, boundary_mode)
+    } else {
+        convolve_fft(heightmap, kernel, boundary_mode)
+    }
+}
+
+fn convolve_direct(heightmap: &[Vec<f32>], kernel: &[Vec<f32>], boundary_mode: BoundaryMode) -> Vec<Vec<f32>> {
+    let radius_y = (kernel.len() / 2) as isize;
+    let radius_x = (kernel[0].len() / 2) as isize;
+
+    (0..heightmap.len() as isize).map(|i|
+        (0..heightmap[0].len() as isize).map(|j| {
+            let mut sum = 0.0;
+            for (a, kernel_row) in kernel.iter().enumerate() {
+                for (b, weight) in kernel_row.iter().enumerate() {
+                    // The kernel is flipped, as in a convolution.
+                    sum += weight * sample(heightmap, i + radius_y - a as isize, j + radius_x - b as isize, boundary_mode);
+                }
+            }
+            sum
+        }).collect()
+    ).collect()
+}
+
+// Pads the heightmap by the radius of the kernel according to the boundary mode, so the circular
+// convolution of the FFT only wraps around within the padding, and crops the padding back off.
+fn convolve_fft(heightmap: &[Vec<f32>], kernel: &[Vec<f32>], boundary_mode: BoundaryMode) -> Vec<Vec<f32>> {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let radius_y = kernel.len() / 2;
+    let radius_x = kernel[0].len() / 2;
+    let padded_height = height + 2 * radius_y;
+    let padded_width = width + 2 * radius_x;
+
+    let mut padded: Vec<Vec<Complex<f32>>> = (0..padded_height).map(|i|
+        (0..padded_width).map(|j| {
+            let value = sample(heightmap, i as isize - radius_y as isize, j as isize - radius_x as isize, boundary_mode);
+            Complex::new(value, 0.0)
+        }).collect()
+    ).collect();
+
+    // Kernel with its center moved to (0, 0), wrapping around.
+    let mut kernel_spectrum = vec![vec![Complex::new(0.0, 0.0); padded_width]; padded_height];
+    for (a, kernel_row) in kernel.iter().enumerate() {
+        let i = (a + padded_height - radius_y) % padded_height;
+        for (b, &weight) in kernel_row.iter().enumerate() {
+            let j = (b + padded_width - radius_x) % padded_width;
+            kernel_spectrum[i][j] = Complex::new(weight, 0.0);
+        }
+    }
+
+    apply_fft_to_rect_grid(&mut padded);
+    apply_fft_to_rect_grid(&mut kernel_spectrum);
+    for (row, kernel_row) in padded.iter_mut().zip(kernel_spectrum.iter()) {
+        for (value, weight) in row.iter_mut().zip(kernel_row.iter()) {
+            *value *= weight;
+        }
+    }
+    apply_ifft_to_rect_grid(&mut padded);
+
+    padded[radius_y..radius_y + height].iter().map(|row|
+        row[radius_x..radius_x + width].iter().map(|value| value.re).collect()
+    ).collect()
+}
+
+// Sample at row i and column j, which may be outside of the heightmap.
+pub fn sample(heightmap: &[Vec<f32>], i: isize, j: isize, boundary_mode: BoundaryMode) -> f32 {
+    let height = heightmap.len() as isize;
+    let width = heightmap[0].len() as isize;
+
+    if let BoundaryMode::Extrapolate = boundary_mode {
+        // Twice the edge sample minus its mirror image, one axis at a time. Samples further out than the
+        // heightmap is wide mirror onto its opposite edge.
+        if i < 0 || i >= height {
+            let edge = i.clamp(0, height - 1);
+            let mirrored = (2 * edge - i).clamp(0, height - 1);
+            return 2.0 * sample(heightmap, edge, j, boundary_mode) - sample(heightmap, mirrored, j, boundary_mode);
+        }
+        if j < 0 || j >= width {
+            let edge = j.clamp(0, width - 1);
+            let mirrored = (2 * edge - j).clamp(0, width - 1);
+            let row = &heightmap[i as usize];
+            return 2.0 * row[edge as usize] - row[mirrored as usize];
+        }
+        return heightmap[i as usize][j as usize];
+    }
+
+    let inside = |index: isize, size: isize| -> Option<usize> {
+        match boundary_mode {
+            BoundaryMode::Wrap => Some(index.rem_euclid(size) as usize),
+            BoundaryMode::Clamp => Some(index.clamp(0, size - 1) as usize),
+            BoundaryMode::Mirror => {
+                if size == 1 {
+                    return Some(0);
+                }
+                // Reflecting without repeating the edge repeats every 2 * (size - 1) samples.
+                let period = 2 * (size - 1);
+                let folded = index.rem_euclid(period);
+                Some(if folded < size { folded } else { period - folded } as usize)
+            }
+            BoundaryMode::Zero => if index >= 0 && index < size { Some(index as usize) } else { None },
+            BoundaryMode::Extrapolate => unreachable!(),
+        }
+    };
+
+    match (inside(i, height), inside(j, width)) {
+        (Some(i), Some(j)) => heightmap[i][j],
+        _ => 0.0,
+    }
+}
+
+// Normalized gaussian blur kernel, 3 standard deviations wide on each side.
+pub fn gaussian_kernel(sigma: f32) -> Vec<Vec<f32>> {
+    let radius = (3.0 * sigma).ceil().max(1.0) as isize;
+    let mut kernel: Vec<Vec<f32>> = (-radius..=radius).map(|y|
+        (-radius..=radius).map(|x| (-((x * x + y * y) as f32) / (2.0 * sigma * sigma)).exp()).collect()
+    ).collect();
+
+    normalize(&mut kernel);
+    kernel
+}
+
+// Normalized box blur kernel, averaging a (2 * radius + 1) square.
+pub fn box_kernel(radius: usize) -> Vec<Vec<f32>> {
+    let side = 2 * radius + 1;
+    vec![vec![1.0 / (side * side) as f32; side]; side]
+}
+
+// Unsharp mask: the sample plus amount times its difference from the gaussian blur, which sharpens
+// features smaller than about sigma.
+pub fn unsharp_mask_kernel(sigma: f32, amount: f32) -> Vec<Vec<f32>> {
+    let mut kernel = gaussian_kernel(sigma);
+    let center = kernel.len() / 2;
+
+    for row in kernel.iter_mut() {
+        for weight in row.iter_mut() {
+            *weight *= -amount;
+        }
+    }
+    kernel[center][center] += 1.0 + amount;
+
+    kernel
+}
+
+// Discrete 5 point Laplacian.
+pub fn laplacian_kernel() -> Vec<Vec<f32>> {
+    vec![
+        vec![0.0, 1.0, 0.0],
+        vec![1.0, -4.0, 1.0],
+        vec![0.0, 1.0, 0.0],
+    ]
+}
+
+fn normalize(kernel: &mut [Vec<f32>]) {
+    let total: f32 = kernel.iter().flatten().sum();
+    for row in kernel.iter_mut() {
+        for weight in row.iter_mut() {
+            *weight /= total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::noise;
+
+    #[test]
+    fn direct_and_fft_convolution_agree() {
+        let heightmap = noise(20, 13, 5);
+        let kernels = [gaussian_kernel(1.5), box_kernel(2), unsharp_mask_kernel(1.0, 0.5), laplacian_kernel()];
+
+        for boundary_mode in [BoundaryMode::Wrap, BoundaryMode::Clamp, BoundaryMode::Mirror, BoundaryMode::Zero, BoundaryMode::Extrapolate] {
+            for kernel in &kernels {
+                let direct = convolve_direct(&heightmap, kernel, boundary_mode);
+                let fft = convolve_fft(&heightmap, kernel, boundary_mode);
+                for (direct, fft) in direct.iter().flatten().zip(fft.iter().flatten()) {
+                    assert!((direct - fft).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn boundary_modes_read_past_the_edges() {
+        let heightmap = vec![vec![1.0, 2.0, 4.0]];
+        let outside = |j: isize, boundary_mode: BoundaryMode| sample(&heightmap, 0, j, boundary_mode);
+
+        assert_eq!((outside(-1, BoundaryMode::Wrap), outside(3, BoundaryMode::Wrap)), (4.0, 1.0));
+        assert_eq!((outside(-1, BoundaryMode::Clamp), outside(3, BoundaryMode::Clamp)), (1.0, 4.0));
+        assert_eq!((outside(-1, BoundaryMode::Mirror), outside(3, BoundaryMode::Mirror)), (2.0, 2.0));
+        assert_eq!((outside(-1, BoundaryMode::Zero), outside(3, BoundaryMode::Zero)), (0.0, 0.0));
+        assert_eq!((outside(-1, BoundaryMode::Extrapolate), outside(3, BoundaryMode::Extrapolate)), (0.0, 6.0));
+    }
+
+    #[test]
+    fn kernels_keep_or_remove_the_mean() {
+        let plane = vec![vec![3.0; 9]; 9];
+        for kernel in [box_kernel(1), unsharp_mask_kernel(1.0, 2.0)] {
+            assert!(convolve(&plane, &kernel, BoundaryMode::Clamp).iter().flatten().all(|value| (value - 3.0).abs() < 1e-5));
+        }
+        assert!(convolve(&plane, &laplacian_kernel(), BoundaryMode::Clamp).iter().flatten().all(|value| value.abs() < 1e-5));
+    }
+}
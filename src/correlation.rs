@@ -0,0 +1,268 @@
+use crate::fft_utils::{apply_fft_to_rect_grid, apply_ifft_to_rect_grid};
+use rustfft::num_complex::Complex;
+use serde::Serialize;
+use std::f32::consts::PI;
+
+// Autocorrelation and cross-correlation of heightmaps through the FFT (Wiener-Khinchin theorem): the
+// correlation is the inverse transform of the product of one spectrum with the conjugate of the other.
+//
+// Correlation maps of an a x b heightmap with a c x d one are (a + c - 1) x (b + d - 1), with the zero
+// lag at row c - 1 and column d - 1, so the autocorrelation of a heightmap has its zero lag in the center.
+// The heightmaps are zero padded so nothing wraps around, and the correlations are normalized by the
+// total energy, not by how much the heightmaps overlap at each lag, so long lags fade out instead of
+// getting noisy.
+
+// Offset of one heightmap against another that correlates best.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Alignment {
+    // Sample (i, j) of the second heightmap best matches sample (i + dy, j + dx) of the first.
+    pub dx: isize,
+    pub dy: isize,
+    // Normalized correlation at that offset, 1.0 when the overlap matches exactly.
+    pub correlation: f32,
+}
+
+// Distances in samples over which the autocorrelation decays to a threshold, along the principal axes of
+// the terrain.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct CorrelationLengths {
+    // Direction the terrain stays correlated the longest along, in radians counterclockwise from the x
+    // axis, between 0 and PI. Ridges and valleys run along it.
+    pub angle: f32,
+    // Decay distance along the angle, the longest in any direction.
+    pub major: f32,
+    // Decay distance perpendicular to the angle.
+    pub minor: f32,
+    // Shortest decay distance in any direction.
+    pub shortest: f32,
+}
+
+// Normalized autocorrelation of a heightmap with its mean removed, 1.0 at the zero lag.
+pub fn autocorrelation(heightmap: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    cross_correlation(heightmap, heightmap)
+}
+
+// Normalized cross-correlation of two heightmaps of any size, with their means removed. Entry (r, c)
+// correlates sample (i, j) of b with sample (i + r - rows of b + 1, j + c - columns of b + 1) of a.
+pub fn cross_correlation(a: &[Vec<f32>], b: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let mut correlation = unnormalized_cross_correlation(&centered(a), &centered(b));
+
+    let energy = (energy(a) * energy(b)).sqrt();
+    let scale = if energy > 0.0 { 1.0 / energy as f32 } else { 0.0 };
+    for value in correlation.iter_mut().flatten() {
+        *value *= scale;
+    }
+
+    correlation
+}
+
+// Offset that aligns b with a best. Unlike cross_correlation, each offset is normalized by the mean and
+// energy of the two heightmaps where they overlap, so a good match over a small overlap isn't outweighed
+// by a poor one over a large one, and only offsets where they overlap by at least half of the smaller
+// heightmap are considered.
+pub fn best_offset(a: &[Vec<f32>], b: &[Vec<f32>]) -> Alignment {
+    let (a, b) = (centered(a), centered(b));
+    let correlation = unnormalized_cross_correlation(&a, &b);
+    let (sums_a, squares_a) = summed_area_tables(&a);
+    let (sums_b, squares_b) = summed_area_tables(&b);
+
+    let (height_a, width_a) = (a.len() as isize, a[0].len() as isize);
+    let (height_b, width_b) = (b.len() as isize, b[0].len() as isize);
+    let minimum_overlap = (height_a * width_a).min(height_b * width_b) / 2;
+
+    let mut best = Alignment {
+        dx: 0,
+        dy: 0,
+        correlation: f32::NEG_INFINITY,
+    };
+    for (r, row) in correlation.iter().enumerate() {
+        let dy = r as isize - (height_b - 1);
+        // Rows of b that overlap a.
+        let (top, bottom) = ((-dy).max(0), height_b.min(height_a - dy));
+        for (c, &value) in row.iter().enumerate() {
+            let dx = c as isize - (width_b - 1);
+            let (left, right) = ((-dx).max(0), width_b.min(width_a - dx));
+            let overlap = (bottom - top) * (right - left);
+            if overlap < minimum_overlap {
+                continue;
+            }
+
+            let samples = overlap as f64;
+            let sum_b = box_sum(&sums_b, top, left, bottom, right);
+            let sum_a = box_sum(&sums_a, top + dy, left + dx, bottom + dy, right + dx);
+            let variance_b = box_sum(&squares_b, top, left, bottom, right) - sum_b * sum_b / samples;
+            let variance_a = box_sum(&squares_a, top + dy, left + dx, bottom + dy, right + dx) - sum_a * sum_a / samples;
+            let normalization = (variance_a * variance_b).max(0.0).sqrt();
+            let correlation = if normalization > 0.0 {
+                ((value as f64 - sum_a * sum_b / samples) / normalization) as f32
+            } else {
+                0.0
+            };
+
+            if correlation > best.correlation {
+                best = Alignment {
+                    dx,
+                    dy,
+                    correlation,
+                };
+            }
+        }
+    }
+
+    best
+}
+
+// Correlation lengths from an autocorrelation map, following it outwards from the zero lag in every
+// direction until it drops below the threshold. Lags past half the map overlap too little of the
+// heightmap to be meaningful, so that is the longest length reported.
+pub fn correlation_lengths(autocorrelation: &[Vec<f32>], threshold: f32) -> CorrelationLengths {
+    let center_i = (autocorrelation.len() / 2) as f32;
+    let center_j = (autocorrelation[0].len() / 2) as f32;
+    let longest_lag = ((autocorrelation.len() + 1) / 4).min((autocorrelation[0].len() + 1) / 4) as f32;
+    let step = 0.5;
+
+    let decay = |angle: f32| -> f32 {
+        let (sin, cos) = angle.sin_cos();
+        let mut distance = step;
+        while distance < longest_lag && interpolate(autocorrelation, center_i + distance * sin, center_j + distance * cos) > threshold {
+            distance += step;
+        }
+        distance
+    };
+
+    // The autocorrelation is symmetric, so half the directions are enough.
+    let mut lengths = CorrelationLengths {
+        angle: 0.0,
+        major: 0.0,
+        minor: 0.0,
+        shortest: longest_lag,
+    };
+    let distances: Vec<f32> = (0..180).map(|k| decay(k as f32 * PI / 180.0)).collect();
+    lengths.major = distances.iter().fold(0.0_f32, |longest, &distance| longest.max(distance));
+    lengths.shortest = distances.iter().fold(longest_lag, |shortest, &distance| shortest.min(distance));
+
+    // Strongly directional terrain stays correlated past the longest lag over a range of directions, the
+    // angle is the middle of the range. Angles are doubled for the average, since 0 and PI are the same axis.
+    let (sin_sum, cos_sum) = distances.iter().enumerate()
+        .filter(|(_, &distance)| distance == lengths.major)
+        .fold((0.0_f32, 0.0_f32), |(sin_sum, cos_sum), (k, _)| {
+            let (sin, cos) = (2.0 * k as f32 * PI / 180.0).sin_cos();
+            (sin_sum + sin, cos_sum + cos)
+        });
+    lengths.angle = (sin_sum.atan2(cos_sum) / 2.0).rem_euclid(PI);
+    lengths.minor = decay(lengths.angle + PI / 2.0);
+
+    lengths
+}
+
+// Value of a map at a fractional row and column, bilinearly interpolated, 0.0 outside of it.
+fn interpolate(map: &[Vec<f32>], i: f32, j: f32) -> f32 {
+    let sample = |i: isize, j: isize| -> f32 {
+        if i < 0 || j < 0 || i >= map.len() as isize || j >= map[0].len() as isize {
+            0.0
+        } else {
+            map[i as usize][j as usize]
+        }
+    };
+
+    let (i0, j0) = (i.floor(), j.floor());
+    let (ti, tj) = (i - i0, j - j0);
+    let (i0, j0) = (i0 as isize, j0 as isize);
+    let top = sample(i0, j0) * (1.0 - tj) + sample(i0, j0 + 1) * tj;
+    let bottom = sample(i0 + 1, j0) * (1.0 - tj) + sample(i0 + 1, j0 + 1) * tj;
+    top * (1.0 - ti) + bottom * ti
+}
+
+// Cross-correlation of two heightmaps in the layout described at the top, without any normalization.
+fn unnormalized_cross_correlation(a: &[Vec<f32>], b: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let height = a.len() + b.len() - 1;
+    let width = a[0].len() + b[0].len() - 1;
+
+    let spectrum_a = padded_spectrum(a, width, height);
+    let spectrum_b = padded_spectrum(b, width, height);
+    let mut product: Vec<Vec<Complex<f32>>> = spectrum_a.iter().zip(spectrum_b.iter()).map(|(row_a, row_b)|
+        row_a.iter().zip(row_b.iter()).map(|(value_a, value_b)| value_a * value_b.conj()).collect()
+    ).collect();
+    apply_ifft_to_rect_grid(&mut product);
+
+    // Move the zero lag from the corner to (rows of b - 1, columns of b - 1).
+    let (shift_i, shift_j) = (b.len() - 1, b[0].len() - 1);
+    (0..height).map(|r|
+        (0..width).map(|c| product[(r + height - shift_i) % height][(c + width - shift_j) % width].re).collect()
+    ).collect()
+}
+
+// Spectrum of the heightmap zero padded to width x height.
+fn padded_spectrum(heightmap: &[Vec<f32>], width: usize, height: usize) -> Vec<Vec<Complex<f32>>> {
+    let mut spectrum = vec![vec![Complex::new(0.0, 0.0); width]; height];
+    for (row, heightmap_row) in spectrum.iter_mut().zip(heightmap.iter()) {
+        for (value, &sample) in row.iter_mut().zip(heightmap_row.iter()) {
+            *value = Complex::new(sample, 0.0);
+        }
+    }
+    apply_fft_to_rect_grid(&mut spectrum);
+    spectrum
+}
+
+// Heightmap with its mean removed.
+fn centered(heightmap: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let samples = heightmap.iter().map(|row| row.len()).sum::<usize>() as f64;
+    let mean = (heightmap.iter().flatten().map(|&value| value as f64).sum::<f64>() / samples) as f32;
+    heightmap.iter().map(|row| row.iter().map(|&value| value - mean).collect()).collect()
+}
+
+// Sum of the squared differences from the mean.
+fn energy(heightmap: &[Vec<f32>]) -> f64 {
+    centered(heightmap).iter().flatten().map(|&value| (value as f64).powi(2)).sum()
+}
+
+// Summed area tables of the samples and of their squares, with a leading row and column of zeros.
+fn summed_area_tables(heightmap: &[Vec<f32>]) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    let mut sums = vec![vec![0.0; heightmap[0].len() + 1]; heightmap.len() + 1];
+    let mut squares = sums.clone();
+    for (i, row) in heightmap.iter().enumerate() {
+        for (j, &value) in row.iter().enumerate() {
+            let value = value as f64;
+            sums[i + 1][j + 1] = value + sums[i][j + 1] + sums[i + 1][j] - sums[i][j];
+            squares[i + 1][j + 1] = value * value + squares[i][j + 1] + squares[i + 1][j] - squares[i][j];
+        }
+    }
+    (sums, squares)
+}
+
+// Sum over rows top..bottom and columns left..right of a summed area table.
+fn box_sum(summed: &[Vec<f64>], top: isize, left: isize, bottom: isize, right: isize) -> f64 {
+    let (top, left, bottom, right) = (top as usize, left as usize, bottom as usize, right as usize);
+    summed[bottom][right] - summed[top][right] - summed[bottom][left] + summed[top][left]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::noise;
+
+    #[test]
+    fn best_offset_finds_a_cut_out_window() {
+        let a = noise(64, 48, 1);
+        let b: Vec<Vec<f32>> = a[10..42].iter().map(|row| row[5..37].to_vec()).collect();
+
+        let alignment = best_offset(&a, &b);
+        assert_eq!((alignment.dx, alignment.dy), (5, 10));
+        assert!((alignment.correlation - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn best_offset_finds_a_shift_past_the_edges() {
+        // b is a shifted by (-7, 4), with new terrain where it moved past the edges of a.
+        let a = noise(40, 40, 2);
+        let fresh = noise(40, 40, 3);
+        let b: Vec<Vec<f32>> = (0..40).map(|i| (0..40).map(|j| {
+            let (source_i, source_j) = (i as isize + 4, j as isize - 7);
+            if (0..40).contains(&source_i) && (0..40).contains(&source_j) { a[source_i as usize][source_j as usize] } else { fresh[i][j] }
+        }).collect()).collect();
+
+        let alignment = best_offset(&a, &b);
+        assert_eq!((alignment.dx, alignment.dy), (-7, 4));
+        assert!(alignment.correlation > 0.9);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::convolution::{sample, BoundaryMode};
+
+// Slope, aspect and curvature maps of a heightmap, from finite differences over the 3 x 3 neighbourhood
+// of each sample:
+//
+//   z1 z2 z3
+//   z4 z5 z6
+//   z7 z8 z9
+//
+// x runs along the rows (east) and y down the columns (south), so north is the first row of the
+// heightmap, the same as in the slope and aspect histograms of stats.
+
+// Finite difference stencil.
+#[derive(Clone, Copy)]
+pub enum Stencil {
+    // Horn's weighted differences over all 8 neighbours for the first derivatives and Evans-Young's for
+    // the second ones. Smooths a little, which suits noisy or rough heightmaps.
+    Horn,
+    // Zevenbergen and Thorne's differences of the 4 direct neighbours, which fit a surface through all
+    // 9 samples exactly. Sharper but more sensitive to noise.
+    ZevenbergenThorne,
+}
+
+#[derive(Clone, Copy)]
+pub enum SlopeUnit {
+    Degrees,
+    // Rise over run times 100.
+    Percent,
+}
+
+#[derive(Clone, Copy)]
+pub struct DerivativeParams {
+    pub stencil: Stencil,
+    // Distance between neighbouring samples, in the units of the heights.
+    pub cell_size: f32,
+    // How the neighbours of samples on the edges are read. Extrapolate keeps the slope of the edge,
+    // Wrap is right for heightmaps that tile.
+    pub boundary_mode: BoundaryMode,
+}
+
+impl Default for DerivativeParams {
+    fn default() -> Self {
+        DerivativeParams {
+            stencil: Stencil::Horn,
+            cell_size: 1.0,
+            boundary_mode: BoundaryMode::Extrapolate,
+        }
+    }
+}
+
+// First and second partial derivatives of the heightmap at every sample.
+pub struct SurfaceDerivatives {
+    pub dx: Vec<Vec<f32>>,
+    pub dy: Vec<Vec<f32>>,
+    pub dxx: Vec<Vec<f32>>,
+    pub dyy: Vec<Vec<f32>>,
+    pub dxy: Vec<Vec<f32>>,
+}
+
+pub fn surface_derivatives(heightmap: &[Vec<f32>], params: &DerivativeParams) -> SurfaceDerivatives {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let l = params.cell_size;
+
+    let mut derivatives = SurfaceDerivatives {
+        dx: vec![vec![0.0; width]; height],
+        dy: vec![vec![0.0; width]; height],
+        dxx: vec![vec![0.0; width]; height],
+        dyy: vec![vec![0.0; width]; height],
+        dxy: vec![vec![0.0; width]; height],
+    };
+
+    for i in 0..height {
+        for j in 0..width {
+            let z = |di: isize, dj: isize| -> f32 { sample(heightmap, i as isize + di, j as isize + dj, params.boundary_mode) };
+            let (z1, z2, z3) = (z(-1, -1), z(-1, 0), z(-1, 1));
+            let (z4, z5, z6) = (z(0, -1), z(0, 0), z(0, 1));
+            let (z7, z8, z9) = (z(1, -1), z(1, 0), z(1, 1));
+
+            let (dx, dy, dxx, dyy) = match params.stencil {
+                Stencil::Horn => (
+                    ((z3 + 2.0 * z6 + z9) - (z1 + 2.0 * z4 + z7)) / (8.0 * l),
+                    ((z7 + 2.0 * z8 + z9) - (z1 + 2.0 * z2 + z3)) / (8.0 * l),
+                    (z1 + z3 + z4 + z6 + z7 + z9 - 2.0 * (z2 + z5 + z8)) / (3.0 * l * l),
+                    (z1 + z2 + z3 + z7 + z8 + z9 - 2.0 * (z4 + z5 + z6)) / (3.0 * l * l),
+                ),
+                Stencil::ZevenbergenThorne => (
+                    (z6 - z4) / (2.0 * l),
+                    (z8 - z2) / (2.0 * l),
+                    (z4 + z6 - 2.0 * z5) / (l * l),
+                    (z2 + z8 - 2.0 * z5) / (l * l),
+                ),
+            };
+
+            derivatives.dx[i][j] = dx;
+            derivatives.dy[i][j] = dy;
+            derivatives.dxx[i][j] = dxx;
+            derivatives.dyy[i][j] = dyy;
+            derivatives.dxy[i][j] = (z1 + z9 - z3 - z7) / (4.0 * l * l);
+        }
+    }
+
+    derivatives
+}
+
+// Curvatures are positive on convex shapes like ridges and hilltops and negative on concave ones like
+// valleys and pits, in 1 / the units of the cell size.
+impl SurfaceDerivatives {
+    pub fn slope(&self, unit: SlopeUnit) -> Vec<Vec<f32>> {
+        self.map(|p, q, _, _, _| {
+            let gradient = (p * p + q * q).sqrt();
+            match unit {
+                SlopeUnit::Degrees => gradient.atan().to_degrees(),
+                SlopeUnit::Percent => gradient * 100.0,
+            }
+        })
+    }
+
+    // Direction the slope faces, downhill, in degrees clockwise from north. Flat samples have no aspect
+    // and get -1.0.
+    pub fn aspect(&self) -> Vec<Vec<f32>> {
+        self.map(|p, q, _, _, _| {
+            if p == 0.0 && q == 0.0 {
+                -1.0
+            } else {
+                // Downhill is against the gradient, north is -y and east is x.
+                (-p).atan2(q).to_degrees().rem_euclid(360.0)
+            }
+        })
+    }
+
+    // Curvature along the direction of steepest slope. Positive where flow speeds up over a convex
+    // break of slope, negative where it slows down in a concave one.
+    pub fn profile_curvature(&self) -> Vec<Vec<f32>> {
+        self.map(|p, q, r, t, s| {
+            let gradient_squared = p * p + q * q;
+            if gradient_squared == 0.0 {
+                return 0.0;
+            }
+            -(p * p * r + 2.0 * p * q * s + q * q * t) / (gradient_squared * (1.0 + gradient_squared).powf(1.5))
+        })
+    }
+
+    // Curvature of the contour lines. Positive on spurs where flow spreads out, negative in hollows
+    // where it converges.
+    pub fn plan_curvature(&self) -> Vec<Vec<f32>> {
+        self.map(|p, q, r, t, s| {
+            let gradient_squared = p * p + q * q;
+            if gradient_squared == 0.0 {
+                return 0.0;
+            }
+            -(q * q * r - 2.0 * p * q * s + p * p * t) / gradient_squared.powf(1.5)
+        })
+    }
+
+    // Mean of the principal curvatures of the surface.
+    pub fn mean_curvature(&self) -> Vec<Vec<f32>> {
+        self.map(|p, q, r, t, s| {
+            -((1.0 + q * q) * r - 2.0 * p * q * s + (1.0 + p * p) * t) / (2.0 * (1.0 + p * p + q * q).powf(1.5))
+        })
+    }
+
+    // Sum of the second derivatives, negative on hilltops and positive in pits like any Laplacian.
+    pub fn laplacian(&self) -> Vec<Vec<f32>> {
+        self.map(|_, _, r, t, _| r + t)
+    }
+
+    // Map of a function of dx, dy, dxx, dyy and dxy.
+    fn map(&self, f: impl Fn(f32, f32, f32, f32, f32) -> f32) -> Vec<Vec<f32>> {
+        (0..self.dx.len()).map(|i|
+            (0..self.dx[0].len()).map(|j| f(self.dx[i][j], self.dy[i][j], self.dxx[i][j], self.dyy[i][j], self.dxy[i][j])).collect()
+        ).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Heightmap of f(x, y) on a 9 x 9 grid centred on the origin, x along the rows and y down the columns.
+    fn surface(f: impl Fn(f32, f32) -> f32) -> Vec<Vec<f32>> {
+        (0..9).map(|i| (0..9).map(|j| f(j as f32 - 4.0, i as f32 - 4.0)).collect()).collect()
+    }
+
+    fn params(stencil: Stencil) -> DerivativeParams {
+        DerivativeParams { stencil, cell_size: 2.0, boundary_mode: BoundaryMode::Extrapolate }
+    }
+
+    #[test]
+    fn inclined_plane_has_a_constant_slope_and_no_curvature() {
+        // Rises 1.5 per cell of 2 to the east and 0.5 to the south, so it faces north west and downhill is
+        // atan(0.75 / 0.25) west of north. Extrapolate carries the slope through the edges.
+        let plane = surface(|x, y| 1.5 * x + 0.5 * y);
+        let expected_slope = (0.75_f32.powi(2) + 0.25_f32.powi(2)).sqrt();
+        let expected_aspect = 360.0 - 3.0_f32.atan().to_degrees();
+
+        for stencil in [Stencil::Horn, Stencil::ZevenbergenThorne] {
+            let derivatives = surface_derivatives(&plane, &params(stencil));
+            let degrees = derivatives.slope(SlopeUnit::Degrees);
+            let percent = derivatives.slope(SlopeUnit::Percent);
+            let aspect = derivatives.aspect();
+            let curvatures = [derivatives.profile_curvature(), derivatives.plan_curvature(), derivatives.mean_curvature(), derivatives.laplacian()];
+
+            for i in 0..9 {
+                for j in 0..9 {
+                    assert!((degrees[i][j] - expected_slope.atan().to_degrees()).abs() < 1e-3, "slope {} at ({}, {})", degrees[i][j], i, j);
+                    assert!((percent[i][j] - 100.0 * expected_slope).abs() < 1e-3);
+                    assert!((aspect[i][j] - expected_aspect).abs() < 1e-3);
+                    assert!(curvatures.iter().all(|curvature| curvature[i][j].abs() < 1e-5));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn paraboloids_curve_the_right_way() {
+        for stencil in [Stencil::Horn, Stencil::ZevenbergenThorne] {
+            for a in [0.25, -0.25] {
+                // A pit for a > 0 and a hilltop for a < 0, with Laplacian 4a.
+                let params = DerivativeParams { cell_size: 1.0, ..params(stencil) };
+                let derivatives = surface_derivatives(&surface(|x, y| a * (x * x + y * y)), &params);
+                let laplacian = derivatives.laplacian();
+                let (profile, plan, mean) = (derivatives.profile_curvature(), derivatives.plan_curvature(), derivatives.mean_curvature());
+
+                for i in 1..8 {
+                    for j in 1..8 {
+                        assert!((laplacian[i][j] - 4.0 * a).abs() < 1e-5, "laplacian {} at ({}, {})", laplacian[i][j], i, j);
+                        assert!(mean[i][j] * a < 0.0);
+                        if (i, j) != (4, 4) {
+                            assert!(profile[i][j] * a < 0.0 && plan[i][j] * a < 0.0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ridges_are_convex_and_valleys_concave_across_them() {
+        // Ridge and valley along the x axis, seen from their flank at y = 2.
+        for (a, sign) in [(-0.5, 1.0), (0.5, -1.0)] {
+            let derivatives = surface_derivatives(&surface(|_, y| a * y * y), &params(Stencil::Horn));
+            assert!(derivatives.profile_curvature()[6][4] * sign > 0.0);
+            assert!(derivatives.mean_curvature()[6][4] * sign > 0.0);
+            // The contours are straight.
+            assert!(derivatives.plan_curvature()[6][4].abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+// Where water flows over a heightmap and which drainage basin every sample belongs to, for placing
+// rivers and regions.
+//
+// Flow directions are D8: every sample drains to the one of its 8 neighbours down the steepest slope.
+// Samples of flats, which have no lower neighbour, drain across the flat towards its nearest sample that
+// does, and a pit or a flat with no way down is a sink where the water stays. Sinks on the edge of the
+// heightmap are outlets, where the water leaves it. Everything works on flat arrays of samples and
+// queues, without recursion, so it scales to 4096 x 4096 heightmaps.
+
+// Row and column offsets of the neighbour each flow direction points to: east, southeast, south,
+// southwest, west, northwest, north and northeast, with north the first row of the heightmap.
+pub const D8: [(isize, isize); 8] = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+// Flow direction of sinks, which don't drain to another sample.
+pub const SINK: u8 = u8::MAX;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Basin {
+    // Label of the samples in the basin.
+    pub id: u32,
+    // Row and column of the sink or outlet the basin drains to.
+    pub outlet: (usize, usize),
+    // Whether the outlet is on the edge of the heightmap, where the water leaves it, rather than a sink
+    // in a closed depression.
+    pub on_edge: bool,
+    // Number of samples in the basin.
+    pub area: usize,
+    // Height of the highest sample of the basin above the outlet.
+    pub relief: f32,
+}
+
+pub struct DrainageBasins {
+    // Basin id of every sample.
+    pub labels: Vec<Vec<u32>>,
+    // The basins by id.
+    pub basins: Vec<Basin>,
+}
+
+// Heightmap with its closed depressions filled up to the height where they would spill over, so water
+// can flow from everywhere to the edge (Priority-Flood, Barnes et al.). The heightmap is flooded from the
+// edge inwards, always from the lowest sample reached so far, and samples below the water level are
+// raised to it.
+pub fn fill_depressions(heightmap: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let mut filled: Vec<f32> = heightmap.iter().flatten().copied().collect();
+    let mut visited = vec![false; filled.len()];
+
+    let mut queue = BinaryHeap::new();
+    for i in 0..height {
+        for j in 0..width {
+            if i == 0 || j == 0 || i == height - 1 || j == width - 1 {
+                let cell = i * width + j;
+                visited[cell] = true;
+                queue.push(FloodCell { height: filled[cell], cell });
+            }
+        }
+    }
+
+    // Samples at or below the water level are flooded in the order they're reached, without the cost of
+    // the priority queue.
+    let mut pits = VecDeque::new();
+    while let Some(cell) = pits.pop_front().or_else(|| queue.pop().map(|flooded: FloodCell| flooded.cell)) {
+        for neighbour in neighbours(cell, width, height) {
+            if visited[neighbour] {
+                continue;
+            }
+            visited[neighbour] = true;
+            if filled[neighbour] <= filled[cell] {
+                filled[neighbour] = filled[cell];
+                pits.push_back(neighbour);
+            } else {
+                queue.push(FloodCell { height: filled[neighbour], cell: neighbour });
+            }
+        }
+    }
+
+    filled.chunks(width).map(|row| row.to_vec()).collect()
+}
+
+// D8 flow direction of every sample, an index into D8 or SINK. With fill the directions are those of the
+// heightmap with its depressions filled, so all the water reaches the edge, otherwise every pit and
+// closed flat is a sink.
+pub fn flow_directions(heightmap: &[Vec<f32>], fill: bool) -> Vec<Vec<u8>> {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let heights: Vec<f32> = if fill {
+        fill_depressions(heightmap).into_iter().flatten().collect()
+    } else {
+        heightmap.iter().flatten().copied().collect()
+    };
+
+    const UNRESOLVED: u8 = SINK - 1;
+    let mut directions = vec![UNRESOLVED; heights.len()];
+
+    // Steepest descent wherever there's a lower neighbour.
+    for (cell, direction) in directions.iter_mut().enumerate() {
+        let (i, j) = ((cell / width) as isize, (cell % width) as isize);
+        let mut steepest = 0.0;
+        for (d, &(di, dj)) in D8.iter().enumerate() {
+            let (y, x) = (i + di, j + dj);
+            if y < 0 || y >= height as isize || x < 0 || x >= width as isize {
+                continue;
+            }
+            let distance = if di != 0 && dj != 0 { 2.0_f32.sqrt() } else { 1.0 };
+            let slope = (heights[cell] - heights[y as usize * width + x as usize]) / distance;
+            if slope > steepest {
+                steepest = slope;
+                *direction = d as u8;
+            }
+        }
+    }
+
+    // Flats drain towards the samples at their height that already drain somewhere, spreading out from
+    // them one neighbour at a time.
+    let mut queue: VecDeque<usize> = (0..heights.len()).filter(|&cell|
+        directions[cell] != UNRESOLVED
+            && neighbours(cell, width, height).any(|neighbour| directions[neighbour] == UNRESOLVED && heights[neighbour] == heights[cell])
+    ).collect();
+    drain_flats(&heights, &mut directions, &mut queue, width, height, UNRESOLVED);
+
+    // What's left are pits and closed flats. Each gets one sink, on the edge if it touches it, and the
+    // rest of the flat drains to it.
+    let on_edge = |cell: usize| {
+        let (i, j) = (cell / width, cell % width);
+        i == 0 || j == 0 || i == height - 1 || j == width - 1
+    };
+    let edge_first = (0..heights.len()).filter(|&cell| on_edge(cell)).chain((0..heights.len()).filter(|&cell| !on_edge(cell)));
+    for cell in edge_first {
+        if directions[cell] == UNRESOLVED {
+            directions[cell] = SINK;
+            queue.push_back(cell);
+            drain_flats(&heights, &mut directions, &mut queue, width, height, UNRESOLVED);
+        }
+    }
+
+    directions.chunks(width).map(|row| row.to_vec()).collect()
+}
+
+// Drainage basin of every sample, the sink or outlet it drains to following the flow directions, with
+// the area and relief of each basin. Labels are spread upstream from every sink, so no flow path is ever
+// followed sample by sample.
+pub fn drainage_basins(heightmap: &[Vec<f32>], directions: &[Vec<u8>]) -> DrainageBasins {
+    let height = directions.len();
+    let width = directions[0].len();
+    let directions: Vec<u8> = directions.iter().flatten().copied().collect();
+
+    let mut labels = vec![u32::MAX; directions.len()];
+    let mut basins = Vec::new();
+    let mut queue = VecDeque::new();
+    for sink in (0..directions.len()).filter(|&cell| directions[cell] == SINK) {
+        let id = basins.len() as u32;
+        let (row, column) = (sink / width, sink % width);
+        let outlet_height = heightmap[row][column];
+        let mut basin = Basin {
+            id,
+            outlet: (row, column),
+            on_edge: row == 0 || column == 0 || row == height - 1 || column == width - 1,
+            area: 0,
+            relief: 0.0,
+        };
+
+        labels[sink] = id;
+        queue.push_back(sink);
+        while let Some(cell) = queue.pop_front() {
+            basin.area += 1;
+            basin.relief = basin.relief.max(heightmap[cell / width][cell % width] - outlet_height);
+            for neighbour in neighbours(cell, width, height) {
+                if labels[neighbour] == u32::MAX && directions[neighbour] != SINK && downstream(neighbour, directions[neighbour], width) == cell {
+                    labels[neighbour] = id;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        basins.push(basin);
+    }
+
+    DrainageBasins {
+        labels: labels.chunks(width).map(|row| row.to_vec()).collect(),
+        basins,
+    }
+}
+
+// Points the unresolved neighbours at the height of each queued sample to it, and queues them in turn.
+fn drain_flats(heights: &[f32], directions: &mut [u8], queue: &mut VecDeque<usize>, width: usize, height: usize, unresolved: u8) {
+    while let Some(cell) = queue.pop_front() {
+        let (i, j) = ((cell / width) as isize, (cell % width) as isize);
+        for (d, &(di, dj)) in D8.iter().enumerate() {
+            let (y, x) = (i + di, j + dj);
+            if y < 0 || y >= height as isize || x < 0 || x >= width as isize {
+                continue;
+            }
+            let neighbour = y as usize * width + x as usize;
+            if directions[neighbour] == unresolved && heights[neighbour] == heights[cell] {
+                // The neighbour drains back the opposite way.
+                directions[neighbour] = ((d + 4) % 8) as u8;
+                queue.push_back(neighbour);
+            }
+        }
+    }
+}
+
+// Sample a direction points to.
+fn downstream(cell: usize, direction: u8, width: usize) -> usize {
+    let (di, dj) = D8[direction as usize];
+    ((cell / width) as isize + di) as usize * width + ((cell % width) as isize + dj) as usize
+}
+
+// The up to 8 neighbours of a sample.
+fn neighbours(cell: usize, width: usize, height: usize) -> impl Iterator<Item = usize> {
+    let (i, j) = ((cell / width) as isize, (cell % width) as isize);
+    D8.iter().filter_map(move |&(di, dj)| {
+        let (y, x) = (i + di, j + dj);
+        if y < 0 || y >= height as isize || x < 0 || x >= width as isize {
+            None
+        } else {
+            Some(y as usize * width + x as usize)
+        }
+    })
+}
+
+// Sample in the flood queue, ordered so the lowest comes out first.
+struct FloodCell {
+    height: f32,
+    cell: usize,
+}
+
+impl Ord for FloodCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.height.total_cmp(&self.height).then_with(|| other.cell.cmp(&self.cell))
+    }
+}
+
+impl PartialOrd for FloodCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for FloodCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FloodCell {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 9 x 9 bowl, 0 deep in the middle and rising by 1 per ring, in a rim 5 high with a notch 3 high in
+    // the middle of the first row.
+    fn bowl() -> Vec<Vec<f32>> {
+        let mut heightmap: Vec<Vec<f32>> = (0..9_isize).map(|i| (0..9_isize).map(|j| (i - 4).abs().max((j - 4).abs()) as f32).collect()).collect();
+        for (i, row) in heightmap.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                if i == 0 || j == 0 || i == 8 || j == 8 {
+                    *value = 5.0;
+                }
+            }
+        }
+        heightmap[0][4] = 3.0;
+        heightmap
+    }
+
+    // 5 x 12 slope falling to the west edge, with a terrace 4 high over columns 4 to 8.
+    fn terrace() -> Vec<Vec<f32>> {
+        let column = |j: usize| if j < 4 { j } else if j <= 8 { 4 } else { j - 4 };
+        vec![(0..12).map(|j| column(j) as f32).collect(); 5]
+    }
+
+    // 9 x 11 divide along column 6, between a valley on the west edge and a steeper one on the east edge.
+    fn divide() -> Vec<Vec<f32>> {
+        vec![(0..11).map(|j| (j as f32).min(1.5 * (10 - j) as f32)).collect(); 9]
+    }
+
+    // Sample every sample ends up at following the flow directions.
+    fn destination(directions: &[Vec<u8>], mut row: usize, mut column: usize) -> (usize, usize) {
+        let width = directions[0].len();
+        for _ in 0..directions.len() * width {
+            if directions[row][column] == SINK {
+                return (row, column);
+            }
+            let cell = downstream(row * width + column, directions[row][column], width);
+            (row, column) = (cell / width, cell % width);
+        }
+        panic!("flow from ({}, {}) never reaches a sink", row, column);
+    }
+
+    fn outlets(directions: &[Vec<u8>]) -> Vec<(usize, usize)> {
+        let mut outlets: Vec<(usize, usize)> = (0..directions.len())
+            .flat_map(|i| (0..directions[0].len()).map(move |j| (i, j)))
+            .map(|(i, j)| destination(directions, i, j))
+            .collect();
+        outlets.sort();
+        outlets.dedup();
+        outlets
+    }
+
+    #[test]
+    fn depressions_fill_up_to_their_spill_point() {
+        let heightmap = bowl();
+        let filled = fill_depressions(&heightmap);
+
+        for (i, row) in filled.iter().enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                let expected = if i == 0 || j == 0 || i == 8 || j == 8 { heightmap[i][j] } else { 3.0 };
+                assert_eq!(value, expected, "({}, {})", i, j);
+            }
+        }
+        assert_eq!(fill_depressions(&divide()), divide());
+    }
+
+    #[test]
+    fn a_filled_bowl_drains_through_its_notch() {
+        let heightmap = bowl();
+
+        // Unfilled, the middle of the bowl is a sink that everything flows into, the notch included.
+        let directions = flow_directions(&heightmap, false);
+        assert_eq!(outlets(&directions), [(4, 4)]);
+        let basins = drainage_basins(&heightmap, &directions);
+        assert_eq!(basins.basins.len(), 1);
+        let basin = basins.basins[0];
+        assert_eq!((basin.outlet, basin.on_edge, basin.area, basin.relief), ((4, 4), false, 81, 5.0));
+
+        // Filled, the bowl is a flat touching the edge at the notch, which becomes its outlet.
+        let directions = flow_directions(&heightmap, true);
+        assert_eq!(outlets(&directions), [(0, 4)]);
+        let basins = drainage_basins(&heightmap, &directions);
+        assert_eq!(basins.basins.len(), 1);
+        let basin = basins.basins[0];
+        assert_eq!((basin.outlet, basin.on_edge, basin.area, basin.relief), ((0, 4), true, 81, 2.0));
+        assert!(basins.labels.iter().flatten().all(|&label| label == 0));
+    }
+
+    #[test]
+    fn terraces_drain_across_towards_the_lower_side() {
+        let directions = flow_directions(&terrace(), false);
+
+        for row in &directions {
+            for &direction in &row[1..] {
+                assert_ne!(direction, SINK);
+                assert_eq!(D8[direction as usize].1, -1, "{:?}", row);
+            }
+        }
+        // The west edge is a flat with no way down, drained by one sink at its first sample.
+        assert_eq!(outlets(&directions), [(0, 0)]);
+
+        let basins = drainage_basins(&terrace(), &directions);
+        assert_eq!(basins.basins.len(), 1);
+        let basin = basins.basins[0];
+        assert_eq!((basin.outlet, basin.on_edge, basin.area, basin.relief), ((0, 0), true, 60, 7.0));
+    }
+
+    #[test]
+    fn a_divide_separates_two_basins() {
+        let heightmap = divide();
+        let directions = flow_directions(&heightmap, true);
+        assert_eq!(outlets(&directions), [(0, 0), (0, 10)]);
+
+        let basins = drainage_basins(&heightmap, &directions);
+        // The crest drains down the steeper side, to the east.
+        for row in &basins.labels {
+            assert_eq!(row, &[0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1]);
+        }
+        let (west, east) = (basins.basins[0], basins.basins[1]);
+        assert_eq!((west.id, west.outlet, west.area, west.relief), (0, (0, 0), 54, 5.0));
+        assert_eq!((east.id, east.outlet, east.area, east.relief), (1, (0, 10), 45, 6.0));
+        assert!(west.on_edge && east.on_edge);
+    }
+}
@@ -1,19 +1,17 @@
 use rustfft::FftPlanner;
 use rustfft::num_complex::Complex;
-extern crate rand;
-use rand::thread_rng;
-use rand::Rng;
 use std::sync::Arc; 
+use crate::filters::Anisotropy;
 
 //Applies fft to a vector
-pub fn apply_fft_to_vec(vec: &mut Vec<Complex<f32>>, fft: &Arc<dyn rustfft::Fft<f32>>) {
+pub fn apply_fft_to_vec(vec: &mut [Complex<f32>], fft: &Arc<dyn rustfft::Fft<f32>>) {
     fft.process(vec);
 } 
 
 //NOTE: This is a local implementation of FFT in Rust. It is not used for the purpose
 //of this project due to a noticeable difference between this and the highly optimized
 //RustFFT crate.
-pub fn apply_fft(vec: &mut Vec<Complex<f32>>) {
+pub fn apply_fft(vec: &mut [Complex<f32>]) {
     let n = vec.len();
 
     // Rearrange the elements in the vector by bit-reversed indices
@@ -40,14 +38,14 @@ pub fn apply_fft(vec: &mut Vec<Complex<f32>>) {
                 let u = vec[k + j];
                 vec[k + j] = u + t;
                 vec[k + j + m/2] = u - t;
-                w = w * w_m;
+                w *= w_m;
             }
         }
     }
 }
 
 //Applies fft to a grid by applying fft row wise and column wise
-pub fn apply_fft_to_grid(heightmap: &mut Vec<Vec<Complex<f32>>>, size: usize) {
+pub fn apply_fft_to_grid(heightmap: &mut [Vec<Complex<f32>>], size: usize) {
 
     let mut planner: FftPlanner<f32> = FftPlanner::new();
     let fft = planner.plan_fft_forward(size);
@@ -79,7 +77,7 @@ pub fn apply_fft_to_grid(heightmap: &mut Vec<Vec<Complex<f32>>>, size: usize) {
 }
 
 //Applies ifft by applying ifft row wise and column wise with a scaling factor
-pub fn apply_ifft_to_grid(heightmap: &mut Vec<Vec<Complex<f32>>>, size: usize) {
+pub fn apply_ifft_to_grid(heightmap: &mut [Vec<Complex<f32>>], size: usize) {
 
     let mut planner = FftPlanner::new();
     let ifft = planner.plan_fft_inverse(size);
@@ -94,7 +92,7 @@ pub fn apply_ifft_to_grid(heightmap: &mut Vec<Vec<Complex<f32>>>, size: usize) {
 
     for row in heightmap.iter_mut() {
         for value in row.iter_mut() {
-            *value = *value * scale_factor;
+            *value *= scale_factor;
         }
     }
 
@@ -113,7 +111,7 @@ pub fn apply_ifft_to_grid(heightmap: &mut Vec<Vec<Complex<f32>>>, size: usize) {
     // Apply scaling factor after IFFT
     for row in transposed.iter_mut() {
         for value in row.iter_mut() {
-            *value = *value * scale_factor;
+            *value *= scale_factor;
         }
     }
 
@@ -125,33 +123,133 @@ pub fn apply_ifft_to_grid(heightmap: &mut Vec<Vec<Complex<f32>>>, size: usize) {
 }
 
 //Pink noise filter function. Pink noise filter provided better results than other attempted
-//filters.
-pub fn apply_pink_noise_filter(heightmap: &mut Vec<Vec<Complex<f32>>>, size: usize) {
-    let center = size as f32 / 2.0;
+//filters. It is the anisotropic filter below without any stretch or spread.
+pub fn apply_pink_noise_filter(heightmap: &mut [Vec<Complex<f32>>], size: usize) {
+    apply_anisotropic_pink_noise_filter(heightmap, size, &Anisotropy { angle: 0.0, ratio: 1.0, spread: None });
+}
 
-    for i in 0..size {
-        for j in 0..size {
-            let distance = (((i as f32 - center).powi(2) + (j as f32 - center).powi(2)).sqrt()).abs().max(1.0); 
-            let attenuation = (1.0) / (distance.powf(2.0) * 0.15); 
-            
-            heightmap[i][j] *= attenuation;
+//Pink noise filter with directional weighting. The attenuation is 1 / (0.15 f^2), with f the stretched
+//frequency of the anisotropy in cycles per grid, times its angular weight, so it gives aligned ridges.
+pub fn apply_anisotropic_pink_noise_filter(heightmap: &mut [Vec<Complex<f32>>], size: usize, anisotropy: &Anisotropy) {
+    for (i, row) in heightmap.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            let (fx, fy) = (frequency_bin(j, size), frequency_bin(i, size));
+            let distance = anisotropy.radius(fx, fy).max(1.0);
+            let attenuation = anisotropy.angular_weight(fx, fy) / (distance.powf(2.0) * 0.15);
+
+            *value *= attenuation;
         }
     }
 }
 
-pub fn apply_low_pass_filter(heightmap: &mut Vec<Vec<Complex<f32>>>, size: usize) {
-   let center = size as f32 / 2.0;
+// Signed frequency of bin k of an FFT of length size, in cycles per size samples. The first half of the
+// bins holds the positive frequencies and the second half the negative ones.
+pub fn frequency_bin(k: usize, size: usize) -> f32 {
+    if k <= size / 2 {
+        k as f32
+    } else {
+        k as f32 - size as f32
+    }
+}
 
-    for i in 0..size {
-        for j in 0..size {
-            let distance = (((i as f32 - center).powi(2) + (j as f32 - center).powi(2)).sqrt()).max(1.0);
-            let attenuation = if distance < 3.5 {
-                Complex::new(1.0, 1.0)
-            } else {
-                Complex::new(0.0, 0.0)
-            };  
+// Removes every frequency at or below cutoff_x along the rows and cutoff_y along the columns, in cycles
+// per grid, from a spectrum produced by apply_fft_to_rect_grid. What's left is the part of the grid that a
+// grid of 2 * cutoff_x by 2 * cutoff_y samples can't represent.
+pub fn apply_high_pass_filter(spectrum: &mut [Vec<Complex<f32>>], cutoff_x: f32, cutoff_y: f32) {
+    let height = spectrum.len();
+    let width = spectrum[0].len();
+    for (i, row) in spectrum.iter_mut().enumerate() {
+        if frequency_bin(i, height).abs() > cutoff_y {
+            continue;
+        }
+        for (j, value) in row.iter_mut().enumerate() {
+            if frequency_bin(j, width).abs() <= cutoff_x {
+                *value = Complex::new(0.0, 0.0);
+            }
+        }
+    }
+}
 
-            heightmap[i][j] *= attenuation;
+// Applies fft to a grid of any width and height, row wise and then column wise.
+pub fn apply_fft_to_rect_grid(grid: &mut [Vec<Complex<f32>>]) {
+    let mut planner: FftPlanner<f32> = FftPlanner::new();
+    let row_fft = planner.plan_fft_forward(grid[0].len());
+    let column_fft = planner.plan_fft_forward(grid.len());
+
+    apply_fft_to_rect_grid_with(grid, &row_fft, &column_fft);
+}
+
+// Applies ifft to a grid of any width and height, scaled so that it undoes apply_fft_to_rect_grid.
+pub fn apply_ifft_to_rect_grid(grid: &mut [Vec<Complex<f32>>]) {
+    let mut planner: FftPlanner<f32> = FftPlanner::new();
+    let row_ifft = planner.plan_fft_inverse(grid[0].len());
+    let column_ifft = planner.plan_fft_inverse(grid.len());
+
+    apply_fft_to_rect_grid_with(grid, &row_ifft, &column_ifft);
+
+    let scale_factor = 1.0 / (grid.len() * grid[0].len()) as f32;
+    for row in grid.iter_mut() {
+        for value in row.iter_mut() {
+            *value *= scale_factor;
+        }
+    }
+}
+
+fn apply_fft_to_rect_grid_with(grid: &mut [Vec<Complex<f32>>], row_fft: &Arc<dyn rustfft::Fft<f32>>, column_fft: &Arc<dyn rustfft::Fft<f32>>) {
+    for row in grid.iter_mut() {
+        row_fft.process(row);
+    }
+
+    // Columns are copied out, transformed and copied back.
+    let mut column = vec![Complex::new(0.0, 0.0); grid.len()];
+    for j in 0..grid[0].len() {
+        for (value, row) in column.iter_mut().zip(grid.iter()) {
+            *value = row[j];
         }
+        column_fft.process(&mut column);
+        for (value, row) in column.iter().zip(grid.iter_mut()) {
+            row[j] = *value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anisotropic_pink_noise_falls_off_faster_across_the_ridges() {
+        // Ridges along the x axis are 4 times longer than they are wide, so frequencies along x are
+        // attenuated as if they were 4 times higher.
+        let anisotropy = Anisotropy { angle: 0.0, ratio: 4.0, spread: None };
+        let mut spectrum = vec![vec![Complex::new(1.0, 0.0); 16]; 16];
+        apply_anisotropic_pink_noise_filter(&mut spectrum, 16, &anisotropy);
+
+        assert!((spectrum[4][0].re - 1.0 / (0.15 * 16.0)).abs() < 1e-6);
+        assert!((spectrum[0][4].re - 1.0 / (0.15 * 256.0)).abs() < 1e-6);
+        // Negative frequencies are attenuated like the positive ones.
+        assert_eq!(spectrum[0][12], spectrum[0][4]);
+        assert_eq!(spectrum[12][0], spectrum[4][0]);
+    }
+
+    #[test]
+    fn pink_noise_is_anisotropic_pink_noise_without_anisotropy() {
+        let noise: Vec<Vec<Complex<f32>>> = crate::fixtures::noise(16, 16, 3).into_iter()
+            .map(|row| row.into_iter().map(|value| Complex::new(value, -value)).collect())
+            .collect();
+        let mut isotropic = noise.clone();
+        apply_pink_noise_filter(&mut isotropic, 16);
+        let mut anisotropic = noise;
+        apply_anisotropic_pink_noise_filter(&mut anisotropic, 16, &Anisotropy { angle: 0.7, ratio: 1.0, spread: None });
+
+        for (a, b) in isotropic.iter().flatten().zip(anisotropic.iter().flatten()) {
+            assert!((a - b).norm() < 1e-5 * a.norm().max(1.0), "{} != {}", a, b);
+        }
+
+        // The lowest frequencies, in the corners of the unshifted spectrum, are attenuated the least.
+        let mut spectrum = vec![vec![Complex::new(1.0, 0.0); 16]; 16];
+        apply_pink_noise_filter(&mut spectrum, 16);
+        assert_eq!(spectrum[1][0], spectrum[15][0]);
+        assert!(spectrum[1][0].re > spectrum[8][8].re * 100.0);
     }
 }
@@ -0,0 +1,205 @@
+use crate::fft_utils::{apply_fft_to_rect_grid, apply_ifft_to_rect_grid, frequency_bin};
+use rustfft::num_complex::Complex;
+
+// Frequency domain filters that can be applied to any heightmap as a post process.
+//
+// Frequencies are in cycles per sample, from 0 up to the Nyquist frequency of 0.5, so the same filter
+// does the same thing on heightmaps of any size. All filters have a real gain that is symmetric around
+// the origin, so they only scale the spectrum and leave the phase untouched.
+
+// Transition between the frequencies that pass and the ones that are stopped.
+#[derive(Clone, Copy)]
+pub enum FilterShape {
+    // Hard cutoff, rings around sharp features.
+    Ideal,
+    // Gaussian roll off, the cutoff is the standard deviation. Never rings.
+    Gaussian,
+    // Butterworth roll off of the given order, squared, so the gain is half (a quarter of the power) at the
+    // cutoff and a high pass is exactly one minus the low pass. Higher orders get closer to ideal.
+    Butterworth(u32),
+}
+
+pub enum Filter {
+    // Keeps the frequencies below cutoff.
+    LowPass { cutoff: f32, shape: FilterShape },
+    // Keeps the frequencies above cutoff.
+    HighPass { cutoff: f32, shape: FilterShape },
+    // Keeps the frequencies between low and high.
+    BandPass { low: f32, high: f32, shape: FilterShape },
+    // Removes the frequencies within radius of (u, v) and of its mirror (-u, -v), e.g. a repeating pattern.
+    Notch { u: f32, v: f32, radius: f32, shape: FilterShape },
+    // Gain as a function of the radial frequency.
+    Radial(Box<dyn Fn(f32) -> f32>),
+    // Any other filter, seen through the anisotropy: stretched along the ridge direction and weighted by
+    // the angle of the frequency.
+    Anisotropic { filter: Box<Filter>, anisotropy: Anisotropy },
+}
+
+// Directional weighting of a spectrum, for terrain with aligned ridges and valleys like folded mountain
+// belts or dune fields.
+#[derive(Clone, Copy)]
+pub struct Anisotropy {
+    // Direction the ridges run along, in radians counterclockwise from the x axis.
+    pub angle: f32,
+    // How many times longer features are along the ridges than across them, 1.0 is isotropic.
+    pub ratio: f32,
+    // Standard deviation, in radians, of the angle between a frequency and the direction across the
+    // ridges. Frequencies further from that direction are attenuated. None keeps every direction.
+    pub spread: Option<f32>,
+}
+
+impl Anisotropy {
+    // Components of a frequency along the ridges and across them.
+    fn rotate(&self, fx: f32, fy: f32) -> (f32, f32) {
+        let (sin, cos) = self.angle.sin_cos();
+        (fx * cos + fy * sin, -fx * sin + fy * cos)
+    }
+
+    // Frequency with its component along the ridges stretched by the ratio. Using it in place of the
+    // frequency turns circular isolines of a spectrum into ellipses, so features come out ratio times
+    // longer along the ridges.
+    pub fn stretch(&self, fx: f32, fy: f32) -> (f32, f32) {
+        let (along, across) = self.rotate(fx, fy);
+        let along = along * self.ratio;
+        let (sin, cos) = self.angle.sin_cos();
+        (along * cos - across * sin, along * sin + across * cos)
+    }
+
+    // Radius of the stretched frequency.
+    pub fn radius(&self, fx: f32, fy: f32) -> f32 {
+        let (along, across) = self.rotate(fx, fy);
+        ((along * self.ratio).powi(2) + across.powi(2)).sqrt()
+    }
+
+    // Angular weight of a frequency, 1.0 across the ridges falling off towards the ridge direction.
+    pub fn angular_weight(&self, fx: f32, fy: f32) -> f32 {
+        match self.spread {
+            Some(spread) => {
+                let (along, across) = self.rotate(fx, fy);
+                if along == 0.0 && across == 0.0 {
+                    return 1.0;
+                }
+                let angle = along.abs().atan2(across.abs());
+                (-angle * angle / (2.0 * spread * spread)).exp()
+            }
+            None => 1.0,
+        }
+    }
+}
+
+impl Filter {
+    // Gain of the filter at horizontal frequency fx and vertical frequency fy.
+    pub fn gain(&self, fx: f32, fy: f32) -> f32 {
+        let radius = (fx * fx + fy * fy).sqrt();
+
+        match self {
+            Filter::LowPass { cutoff, shape } => low_pass_gain(radius, *cutoff, *shape),
+            Filter::HighPass { cutoff, shape } => 1.0 - low_pass_gain(radius, *cutoff, *shape),
+            Filter::BandPass { low, high, shape } => low_pass_gain(radius, *high, *shape) * (1.0 - low_pass_gain(radius, *low, *shape)),
+            Filter::Notch { u, v, radius: notch_radius, shape } => {
+                let distance = ((fx - u).powi(2) + (fy - v).powi(2)).sqrt();
+                let mirrored_distance = ((fx + u).powi(2) + (fy + v).powi(2)).sqrt();
+                (1.0 - low_pass_gain(distance, *notch_radius, *shape)) * (1.0 - low_pass_gain(mirrored_distance, *notch_radius, *shape))
+            }
+            Filter::Radial(profile) => profile(radius),
+            Filter::Anisotropic { filter, anisotropy } => {
+                let (stretched_fx, stretched_fy) = anisotropy.stretch(fx, fy);
+                filter.gain(stretched_fx, stretched_fy) * anisotropy.angular_weight(fx, fy)
+            }
+        }
+    }
+}
+
+fn low_pass_gain(frequency: f32, cutoff: f32, shape: FilterShape) -> f32 {
+    match shape {
+        FilterShape::Ideal => if frequency <= cutoff { 1.0 } else { 0.0 },
+        FilterShape::Gaussian => (-frequency * frequency / (2.0 * cutoff * cutoff)).exp(),
+        FilterShape::Butterworth(order) => 1.0 / (1.0 + (frequency / cutoff).powi(2 * order as i32)),
+    }
+}
+
+// Multiplies a spectrum, as produced by apply_fft_to_grid or apply_fft_to_rect_grid, by the gain of the filter.
+pub fn apply_filter_to_spectrum(spectrum: &mut [Vec<Complex<f32>>], filter: &Filter) {
+    let height = spectrum.len();
+    let width = spectrum[0].len();
+
+    for (i, row) in spectrum.iter_mut().enumerate() {
+        let fy = frequency_bin(i, height) / height as f32;
+        for (j, value) in row.iter_mut().enumerate() {
+            let fx = frequency_bin(j, width) / width as f32;
+            *value *= filter.gain(fx, fy);
+        }
+    }
+}
+
+// Filters a heightmap of any size.
+pub fn apply_filter(heightmap: &[Vec<f32>], filter: &Filter) -> Vec<Vec<f32>> {
+    let mut spectrum: Vec<Vec<Complex<f32>>> = heightmap.iter().map(|row|
+        row.iter().map(|&height| Complex::new(height, 0.0)).collect()
+    ).collect();
+
+    apply_fft_to_rect_grid(&mut spectrum);
+    apply_filter_to_spectrum(&mut spectrum, filter);
+    apply_ifft_to_rect_grid(&mut spectrum);
+
+    spectrum.into_iter()
+        .map(|row| row.into_iter().map(|c| c.re).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::max_difference;
+    use std::f32::consts::PI;
+
+    // Cosine with the given number of cycles along the rows and the columns of a 64 x 64 heightmap.
+    fn wave(cycles_x: f32, cycles_y: f32, phase: f32) -> Vec<Vec<f32>> {
+        (0..64).map(|i| (0..64).map(|j| (2.0 * PI * (cycles_x * j as f32 + cycles_y * i as f32) / 64.0 + phase).cos()).collect()).collect()
+    }
+
+    fn sum(a: &[Vec<f32>], b: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        a.iter().zip(b.iter()).map(|(a, b)| a.iter().zip(b.iter()).map(|(a, b)| a + b).collect()).collect()
+    }
+
+    #[test]
+    fn low_and_high_pass_split_the_spectrum_without_moving_the_phase() {
+        // 2 and 16 cycles per grid are 0.03 and 0.25 cycles per sample.
+        let low = wave(2.0, 0.0, 0.7);
+        let high = wave(0.0, 16.0, 1.3);
+        let heightmap = sum(&low, &high);
+
+        let low_pass = Filter::LowPass { cutoff: 0.1, shape: FilterShape::Ideal };
+        assert!(max_difference(&apply_filter(&heightmap, &low_pass), &low) < 1e-4);
+        let high_pass = Filter::HighPass { cutoff: 0.1, shape: FilterShape::Ideal };
+        assert!(max_difference(&apply_filter(&heightmap, &high_pass), &high) < 1e-4);
+    }
+
+    #[test]
+    fn notch_removes_a_repeating_pattern() {
+        let hills = wave(1.0, 1.0, 0.0);
+        let pattern = wave(8.0, 4.0, 0.4);
+        let notch = Filter::Notch { u: 8.0 / 64.0, v: 4.0 / 64.0, radius: 0.02, shape: FilterShape::Butterworth(4) };
+        assert!(max_difference(&apply_filter(&sum(&hills, &pattern), &notch), &hills) < 0.01);
+    }
+
+    #[test]
+    fn anisotropic_filters_are_stretched_along_the_ridges() {
+        // Ridges along the x axis, so the filter sees frequencies along x 4 times higher.
+        let filter = Filter::Anisotropic {
+            filter: Box::new(Filter::LowPass { cutoff: 0.1, shape: FilterShape::Ideal }),
+            anisotropy: Anisotropy { angle: 0.0, ratio: 4.0, spread: None },
+        };
+        assert_eq!(filter.gain(0.0, 0.08), 1.0);
+        assert_eq!(filter.gain(0.08, 0.0), 0.0);
+        assert_eq!(filter.gain(0.02, 0.0), 1.0);
+    }
+
+    #[test]
+    fn angular_weight_keeps_the_direction_across_the_ridges() {
+        let anisotropy = Anisotropy { angle: 0.0, ratio: 1.0, spread: Some(0.3) };
+        assert_eq!(anisotropy.angular_weight(0.0, 0.1), 1.0);
+        assert_eq!(anisotropy.angular_weight(0.0, -0.1), 1.0);
+        assert!(anisotropy.angular_weight(0.1, 0.0) < 1e-5);
+    }
+}
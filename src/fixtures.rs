@@ -0,0 +1,24 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Heightmaps and comparisons shared by the tests of the other modules.
+
+// Rolling hills of size x size samples that don't tile.
+pub fn hills(size: usize) -> Vec<Vec<f32>> {
+    (0..size).map(|i| (0..size).map(|j| 10.0 * (i as f32 * 0.3).sin() * (j as f32 * 0.2).cos()).collect()).collect()
+}
+
+// Plane of size x size samples rising by east per column and by south per row.
+pub fn plane(size: usize, east: f32, south: f32) -> Vec<Vec<f32>> {
+    (0..size).map(|i| (0..size).map(|j| east * j as f32 + south * i as f32).collect()).collect()
+}
+
+// Uniform white noise in -1..1, the same for the same seed.
+pub fn noise(width: usize, height: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..height).map(|_| (0..width).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect()
+}
+
+pub fn max_difference(a: &[Vec<f32>], b: &[Vec<f32>]) -> f32 {
+    a.iter().flatten().zip(b.iter().flatten()).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max)
+}
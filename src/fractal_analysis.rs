@@ -1,67 +1,482 @@
-pub fn calculate_fractal_dimension(heightmap: &Vec<Vec<f32>>) -> f32 {
-    let mut sizes = Vec::new();
-    let mut counts = Vec::new();
+use crate::fft_utils::{apply_fft_to_rect_grid, frequency_bin};
+use rustfft::num_complex::Complex;
+use std::f32::consts::PI;
 
-    let mut size = heightmap.len() as i32;
+// Fractal dimension of the surface of a heightmap, between 2.0 for a smooth surface and 3.0 for one so
+// rough it fills space. Fractional Brownian surfaces have D = 3 - H.
+pub fn calculate_fractal_dimension(heightmap: &[Vec<f32>]) -> f32 {
+    differential_box_counting(heightmap, &dyadic_scales(heightmap, 2)).dimension
+}
+
+// Roughness of a surface from one of the estimators below, as the Hurst exponent H and the fractal
+// dimension D = 3 - H, each with a 95% confidence interval from the standard error of the fit.
+#[derive(Clone, Copy, Debug)]
+pub struct RoughnessEstimate {
+    pub hurst: f32,
+    pub hurst_interval: (f32, f32),
+    pub dimension: f32,
+    pub dimension_interval: (f32, f32),
+    // Fit the estimate was read from, its r_squared tells how well the surface follows a single scaling law.
+    pub fit: LineFit,
+}
+
+impl RoughnessEstimate {
+    fn from_hurst(hurst: f32, std_err: f32, fit: LineFit) -> RoughnessEstimate {
+        let margin = 1.96 * std_err;
+        RoughnessEstimate {
+            hurst,
+            hurst_interval: (hurst - margin, hurst + margin),
+            dimension: 3.0 - hurst,
+            dimension_interval: (3.0 - hurst - margin, 3.0 - hurst + margin),
+            fit,
+        }
+    }
+}
+
+// Powers of two from smallest up to an eighth of the shorter side of the heightmap, the scales in cells
+// the estimators are measured at. Larger scales have too few samples to measure reliably.
+pub fn dyadic_scales(heightmap: &[Vec<f32>], smallest: usize) -> Vec<usize> {
+    let cells = heightmap.len().min(heightmap[0].len()) - 1;
+    let mut scales = Vec::new();
+    let mut scale = smallest.max(1);
+    while scale <= (cells / 8).max(2 * smallest) {
+        scales.push(scale);
+        scale *= 2;
+    }
+    scales
+}
+
+// Differential box counting (Sarkar and Chaudhuri). The heightmap is covered with columns of 3D boxes
+// that are scale cells wide and proportionally tall, so the height range spans as many boxes as the
+// shorter side does. Each column counts the boxes between its lowest and highest sample, and the
+// dimension is the slope of the log of the total against the log of the number of boxes per side.
+// Neighbouring columns share their edge samples, so the surface between them is covered too.
+// Like other box counting methods it reads low on very rough surfaces, about 2.55 for D = 2.8 and 2.37
+// for D = 2.5 on 1025 x 1025 fractional Brownian surfaces, and is accurate close to 2.
+pub fn differential_box_counting(heightmap: &[Vec<f32>], scales: &[usize]) -> RoughnessEstimate {
+    let cells = heightmap.len().min(heightmap[0].len()) - 1;
+    let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+    let highest = heightmap.iter().flatten().fold(f32::NEG_INFINITY, |highest, &value| highest.max(value));
+    let range = highest - lowest;
+
+    let mut log_divisions = Vec::new();
+    let mut log_counts = Vec::new();
+    for &scale in scales {
+        if scale == 0 || scale > cells {
+            continue;
+        }
+
+        let box_height = range * scale as f32 / cells as f32;
+        let mut count = 0.0_f64;
+        for i in (0..heightmap.len() - scale).step_by(scale) {
+            for j in (0..heightmap[0].len() - scale).step_by(scale) {
+                if box_height > 0.0 {
+                    let (low, high) = heightmap[i..=i + scale].iter()
+                        .flat_map(|row| row[j..=j + scale].iter())
+                        .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), &value| (low.min(value), high.max(value)));
+                    count += (((high - lowest) / box_height).floor() - ((low - lowest) / box_height).floor() + 1.0) as f64;
+                } else {
+                    count += 1.0;
+                }
+            }
+        }
+
+        log_divisions.push((cells as f32 / scale as f32).ln());
+        log_counts.push(count.ln() as f32);
+    }
 
-    while size > 1 {
-        let count = count_boxes(heightmap, size);
-        sizes.push(size);
-        counts.push(count);
-        size /= 2;
+    let fit = fit_line(&log_divisions, &log_counts);
+    RoughnessEstimate::from_hurst(3.0 - fit.slope, fit.slope_std_err, fit)
+}
+
+// Structure function (variogram) method. Half the mean squared height difference between samples lag
+// cells apart, along rows and columns, grows as lag^(2H).
+pub fn variogram_estimate(heightmap: &[Vec<f32>], lags: &[usize]) -> RoughnessEstimate {
+    let mut log_lags = Vec::new();
+    let mut log_variances = Vec::new();
+    for &lag in lags {
+        if let Some(variance) = semivariogram(heightmap, lag) {
+            log_lags.push((lag as f32).ln());
+            log_variances.push(variance.ln());
+        }
     }
 
-    linear_regression(&sizes, &counts)
+    let fit = fit_line(&log_lags, &log_variances);
+    RoughnessEstimate::from_hurst(fit.slope / 2.0, fit.slope_std_err / 2.0, fit)
 }
 
-fn count_boxes(heightmap: &Vec<Vec<f32>>, size: i32) -> i32 {
-    let mut count = 0;
-    let step = size as usize;
-    let h_len = heightmap.len();
-    let w_len = heightmap[0].len();
+// Empirical semivariogram, half the mean squared height difference between samples lag cells apart along
+// rows and columns. None when the lag is 0 or doesn't fit in the heightmap.
+pub fn semivariogram(heightmap: &[Vec<f32>], lag: usize) -> Option<f32> {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    if lag == 0 || (lag >= width && lag >= height) {
+        return None;
+    }
 
-    for i in (0..h_len).step_by(step) {
-        for j in (0..w_len).step_by(step) {
-            if check_box(heightmap, i, j, step) {
+    let mut sum = 0.0_f64;
+    let mut count = 0_usize;
+    if lag < width {
+        for row in heightmap {
+            for pair in row.iter().zip(row[lag..].iter()) {
+                sum += ((pair.1 - pair.0) as f64).powi(2);
+                count += 1;
+            }
+        }
+    }
+    if lag < height {
+        for (row, lagged_row) in heightmap.iter().zip(heightmap[lag..].iter()) {
+            for pair in row.iter().zip(lagged_row.iter()) {
+                sum += ((pair.1 - pair.0) as f64).powi(2);
                 count += 1;
             }
         }
     }
 
-    count
+    Some((0.5 * sum / count as f64) as f32)
+}
+
+// Detrended fluctuation analysis in two dimensions. The heightmap is cut into squares of scale x scale
+// samples, the best fitting plane is removed from each and the root mean square of what is left grows
+// as scale^H. The heightmap is used as the profile directly, since terrain is already the integrated
+// kind of signal DFA is meant for. Removing the plane makes it insensitive to overall tilt.
+pub fn detrended_fluctuation_analysis(heightmap: &[Vec<f32>], scales: &[usize]) -> RoughnessEstimate {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+
+    let mut log_scales = Vec::new();
+    let mut log_fluctuations = Vec::new();
+    for &scale in scales {
+        if scale < 3 || scale > width || scale > height {
+            continue;
+        }
+
+        let mut sum = 0.0_f64;
+        let mut squares = 0_usize;
+        for i in (0..=height - scale).step_by(scale) {
+            for j in (0..=width - scale).step_by(scale) {
+                sum += plane_residual_variance(heightmap, i, j, scale);
+                squares += 1;
+            }
+        }
+
+        log_scales.push((scale as f32).ln());
+        log_fluctuations.push((sum / squares as f64).sqrt().ln() as f32);
+    }
+
+    let fit = fit_line(&log_scales, &log_fluctuations);
+    RoughnessEstimate::from_hurst(fit.slope, fit.slope_std_err, fit)
+}
+
+// Variance of the samples of a scale x scale square around the least squares plane through them. With
+// coordinates centered on the square, x and y are uncorrelated and the plane fits independently along each.
+fn plane_residual_variance(heightmap: &[Vec<f32>], top: usize, left: usize, scale: usize) -> f64 {
+    let center = (scale - 1) as f64 / 2.0;
+    let samples = (scale * scale) as f64;
+
+    let (mut sum, mut sum_x, mut sum_y, mut sum_squares) = (0.0, 0.0, 0.0, 0.0);
+    for (a, row) in heightmap[top..top + scale].iter().enumerate() {
+        let y = a as f64 - center;
+        for (b, &value) in row[left..left + scale].iter().enumerate() {
+            let x = b as f64 - center;
+            let value = value as f64;
+            sum += value;
+            sum_x += x * value;
+            sum_y += y * value;
+            sum_squares += value * value;
+        }
+    }
+
+    // Sum of x^2 over the square, the same for y.
+    let coordinate_squares = scale as f64 * (0..scale).map(|k| (k as f64 - center).powi(2)).sum::<f64>();
+    let mean = sum / samples;
+    let explained = sum_x * sum_x / coordinate_squares + sum_y * sum_y / coordinate_squares;
+
+    ((sum_squares - samples * mean * mean - explained) / samples).max(0.0)
+}
+
+// Triangular prism surface area method (Clarke). Each square of scale x scale cells is split into four
+// triangles meeting at its center, raised to the mean of the corners, and the total area of the
+// triangles grows as scale^(2 - D). Heights are scaled so the height range matches the shorter side,
+// the same as in differential_box_counting, since the area depends on the ratio of vertical to
+// horizontal units.
+pub fn triangular_prism_estimate(heightmap: &[Vec<f32>], scales: &[usize]) -> RoughnessEstimate {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let cells = height.min(width) - 1;
+    let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+    let highest = heightmap.iter().flatten().fold(f32::NEG_INFINITY, |highest, &value| highest.max(value));
+    let vertical_scale = if highest > lowest { cells as f64 / (highest - lowest) as f64 } else { 1.0 };
+
+    let mut log_scales = Vec::new();
+    let mut log_areas = Vec::new();
+    for &scale in scales {
+        if scale == 0 || scale > cells {
+            continue;
+        }
+
+        let half = scale as f64 / 2.0;
+        let mut area = 0.0_f64;
+        for i in (0..height - scale).step_by(scale) {
+            for j in (0..width - scale).step_by(scale) {
+                let corners = [
+                    heightmap[i][j] as f64 * vertical_scale,
+                    heightmap[i][j + scale] as f64 * vertical_scale,
+                    heightmap[i + scale][j + scale] as f64 * vertical_scale,
+                    heightmap[i + scale][j] as f64 * vertical_scale,
+                ];
+                let center = corners.iter().sum::<f64>() / 4.0;
+
+                // The corners are scale apart and half a diagonal from the center, in order around the square.
+                let offsets = [(-half, -half), (half, -half), (half, half), (-half, half)];
+                for k in 0..4 {
+                    let next = (k + 1) % 4;
+                    let u = [offsets[k].0, offsets[k].1, corners[k] - center];
+                    let v = [offsets[next].0, offsets[next].1, corners[next] - center];
+                    let cross = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+                    area += 0.5 * (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+                }
+            }
+        }
+
+        // Area per unit of ground covered, since the squares that fit cover a little less ground at larger scales.
+        let squares = ((height - 1) / scale) * ((width - 1) / scale);
+        log_scales.push((scale as f32).ln());
+        log_areas.push((area / (squares * scale * scale) as f64).ln() as f32);
+    }
+
+    let fit = fit_line(&log_scales, &log_areas);
+    // log(area per unit of ground) has slope 2 - D.
+    let dimension = 2.0 - fit.slope;
+    RoughnessEstimate::from_hurst(3.0 - dimension, fit.slope_std_err, fit)
+}
+
+// Least squares fit of a line y = slope * x + intercept.
+#[derive(Clone, Copy, Debug)]
+pub struct LineFit {
+    pub slope: f32,
+    pub intercept: f32,
+    // Fraction of the variance of y explained by the line, 1.0 is a perfect fit.
+    pub r_squared: f32,
+    // Standard error of the slope, 0.0 when there are only two points.
+    pub slope_std_err: f32,
+}
+
+pub fn fit_line(x: &[f32], y: &[f32]) -> LineFit {
+    let n = x.len() as f64;
+    let mean_x = x.iter().map(|&value| value as f64).sum::<f64>() / n;
+    let mean_y = y.iter().map(|&value| value as f64).sum::<f64>() / n;
+
+    let (sxx, sxy, syy) = x.iter().zip(y.iter()).fold((0.0, 0.0, 0.0), |(sxx, sxy, syy), (&xi, &yi)| {
+        let dx = xi as f64 - mean_x;
+        let dy = yi as f64 - mean_y;
+        (sxx + dx * dx, sxy + dx * dy, syy + dy * dy)
+    });
+
+    let slope = sxy / sxx;
+    let intercept = mean_y - slope * mean_x;
+    let residual = (syy - slope * sxy).max(0.0);
+    let r_squared = if syy > 0.0 { 1.0 - residual / syy } else { 1.0 };
+    let slope_std_err = if x.len() > 2 { (residual / (n - 2.0) / sxx).sqrt() } else { 0.0 };
+
+    LineFit {
+        slope: slope as f32,
+        intercept: intercept as f32,
+        r_squared: r_squared as f32,
+        slope_std_err: slope_std_err as f32,
+    }
+}
+
+// Radially averaged power spectral density of a heightmap.
+pub struct PowerSpectrum {
+    // Radial frequency of each bin in cycles per sample, from the lowest non-zero frequency up to the
+    // Nyquist frequency of 0.5.
+    pub frequencies: Vec<f32>,
+    // Average power of the frequencies in each bin.
+    pub power: Vec<f32>,
+    // Spectral exponent, the power falls off as frequency^-beta. Fractional Brownian surfaces have
+    // beta = 2H + 2, so white noise is 0.0 and the smoothest fractal terrain is close to 4.0.
+    pub beta: f32,
+    // Fit of log power against log frequency, its slope is -beta.
+    pub fit: LineFit,
 }
 
-fn check_box(heightmap: &Vec<Vec<f32>>, start_i: usize, start_j: usize, size: usize) -> bool {
-    let mut covered = false;
-    for i in start_i..std::cmp::min(start_i + size, heightmap.len()) {
-        for j in start_j..std::cmp::min(start_j + size, heightmap[0].len()) {
-            if heightmap[i][j] > 25.0 {
-                covered = true;
-                break;
+// Measures the power spectrum of a heightmap of any size. The mean is removed and the heightmap is
+// tapered with a Hann window first, so the jump between opposite edges of heightmaps that don't tile
+// doesn't leak power into every frequency and flatten the slope.
+pub fn power_spectrum(heightmap: &[Vec<f32>]) -> PowerSpectrum {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let (spectrum, window_energy) = windowed_spectrum(heightmap);
+
+    // Bins one lowest frequency wide, along the shorter side.
+    let side = width.min(height);
+    let bins = side / 2;
+    let mut sums = vec![0.0_f64; bins + 1];
+    let mut counts = vec![0_usize; bins + 1];
+    for (i, row) in spectrum.iter().enumerate() {
+        let fy = frequency_bin(i, height) / height as f32;
+        for (j, value) in row.iter().enumerate() {
+            let fx = frequency_bin(j, width) / width as f32;
+            let bin = ((fx * fx + fy * fy).sqrt() * side as f32).round() as usize;
+            if bin <= bins {
+                sums[bin] += value.norm_sqr() as f64 / window_energy;
+                counts[bin] += 1;
             }
         }
-        if covered {
-            break;
+    }
+
+    let mut frequencies = Vec::new();
+    let mut power = Vec::new();
+    for bin in 1..=bins {
+        if counts[bin] > 0 && sums[bin] > 0.0 {
+            frequencies.push(bin as f32 / side as f32);
+            power.push((sums[bin] / counts[bin] as f64) as f32);
         }
     }
-    covered
+
+    let log_frequencies: Vec<f32> = frequencies.iter().map(|frequency| frequency.ln()).collect();
+    let log_power: Vec<f32> = power.iter().map(|power| power.ln()).collect();
+    let fit = fit_line(&log_frequencies, &log_power);
+
+    PowerSpectrum {
+        frequencies,
+        power,
+        beta: -fit.slope,
+        fit,
+    }
+}
+
+// Saves the log magnitude of the spectrum of a heightmap as a grayscale PNG, with the zero frequency in
+// the center. Streaks through the center come from features aligned across them, bright spots away from
+// it from repeating patterns.
+pub fn save_spectrum_image(heightmap: &[Vec<f32>], path: &str) -> image::ImageResult<()> {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let (spectrum, _) = windowed_spectrum(heightmap);
+
+    let log_magnitude: Vec<Vec<f32>> = spectrum.iter().map(|row|
+        row.iter().map(|value| (1.0 + value.norm()).ln()).collect()
+    ).collect();
+    let largest = log_magnitude.iter().flatten().fold(0.0_f32, |largest, &value| largest.max(value));
+    let scale = if largest > 0.0 { 255.0 / largest } else { 0.0 };
+
+    let image = image::GrayImage::from_fn(width as u32, height as u32, |x, y| {
+        // Shift the zero frequency from the corner to the center.
+        let i = (y as usize + height / 2) % height;
+        let j = (x as usize + width / 2) % width;
+        image::Luma([(log_magnitude[i][j] * scale) as u8])
+    });
+
+    image.save(path)
+}
+
+// Spectrum of the heightmap with its mean removed and a Hann window applied, and the energy of the
+// window, which the power has to be divided by.
+fn windowed_spectrum(heightmap: &[Vec<f32>]) -> (Vec<Vec<Complex<f32>>>, f64) {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+
+    let mean = heightmap.iter().flatten().map(|&value| value as f64).sum::<f64>() / (width * height) as f64;
+    let hann = |k: usize, size: usize| -> f32 { 0.5 - 0.5 * (2.0 * PI * k as f32 / size as f32).cos() };
+    let window_y: Vec<f32> = (0..height).map(|i| hann(i, height)).collect();
+    let window_x: Vec<f32> = (0..width).map(|j| hann(j, width)).collect();
+
+    let mut spectrum: Vec<Vec<Complex<f32>>> = heightmap.iter().zip(window_y.iter()).map(|(row, weight_y)|
+        row.iter().zip(window_x.iter()).map(|(&value, weight_x)|
+            Complex::new((value - mean as f32) * weight_y * weight_x, 0.0)
+        ).collect()
+    ).collect();
+    apply_fft_to_rect_grid(&mut spectrum);
+
+    let window_energy = window_y.iter().map(|&w| (w * w) as f64).sum::<f64>() * window_x.iter().map(|&w| (w * w) as f64).sum::<f64>();
+
+    (spectrum, window_energy)
 }
 
-fn linear_regression(x: &Vec<i32>, y: &Vec<i32>) -> f32 {
-    let n = x.len() as f32;
-    let (sum_x, sum_y, sum_xx, sum_xy) = x.iter().zip(y.iter()).fold(
-        (0.0, 0.0, 0.0, 0.0),
-        |(sx, sy, sxx, sxy), (&xi, &yi)| {
-            let x = xi as f32;
-            let y = yi as f32;
-            let log_x = x.ln();
-            let log_y = y.ln();
-            (sx + log_x, sy + log_y, sxx + log_x * log_x, sxy + log_x * log_y)
-        },
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fft_utils::apply_ifft_to_rect_grid;
+    use crate::fixtures::plane;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Surface whose power spectrum falls off as frequency^-beta, with random phases. Fractional Brownian
+    // surfaces with Hurst exponent H have beta = 2H + 2.
+    fn power_law_surface(size: usize, beta: f32, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut spectrum: Vec<Vec<Complex<f32>>> = (0..size).map(|i| {
+            let fy = frequency_bin(i, size);
+            (0..size).map(|j| {
+                let fx = frequency_bin(j, size);
+                let frequency = (fx * fx + fy * fy).sqrt();
+                if frequency == 0.0 {
+                    return Complex::new(0.0, 0.0);
+                }
+                Complex::from_polar(frequency.powf(-beta / 2.0), rng.gen_range(0.0..2.0 * PI))
+            }).collect()
+        }).collect();
+        apply_ifft_to_rect_grid(&mut spectrum);
+
+        spectrum.into_iter().map(|row| row.into_iter().map(|value| value.re).collect()).collect()
+    }
+
+    #[test]
+    fn power_spectrum_recovers_the_spectral_exponent() {
+        for beta in [2.6, 3.0, 3.6] {
+            let spectrum = power_spectrum(&power_law_surface(256, beta, 3));
+            assert!((spectrum.beta - beta).abs() < 0.1, "beta = {} estimated as {}", beta, spectrum.beta);
+            assert!(spectrum.fit.r_squared > 0.99);
+            assert_eq!(spectrum.frequencies.last(), Some(&0.5));
+        }
+    }
+
+    #[test]
+    fn estimators_find_a_plane_smooth() {
+        // Plane tilted along both axes, a smooth surface with D = 2.
+        let plane = plane(129, 0.7, 0.3);
+        let scales = dyadic_scales(&plane, 1);
+        for estimate in [differential_box_counting(&plane, &scales), triangular_prism_estimate(&plane, &scales)] {
+            assert!((estimate.dimension - 2.0).abs() < 1e-3, "D = {}", estimate.dimension);
+            assert!(estimate.fit.r_squared > 0.999);
+        }
+        // Height differences grow linearly with the lag, which is H = 1.
+        let variogram = variogram_estimate(&plane, &scales);
+        assert!((variogram.hurst - 1.0).abs() < 1e-3);
+        assert!(variogram.fit.r_squared > 0.999);
+    }
+
+    #[test]
+    fn estimators_find_the_hurst_exponent_of_fractional_brownian_surfaces() {
+        // Tolerances are on H, or on D = 3 - H. DFA reads 0.1 to 0.15 high on these.
+        for hurst in [0.5, 0.8] {
+            let surface = power_law_surface(256, 2.0 * hurst + 2.0, 7);
+            let estimates = [
+                (variogram_estimate(&surface, &dyadic_scales(&surface, 1)), 0.1),
+                (detrended_fluctuation_analysis(&surface, &dyadic_scales(&surface, 4)), 0.2),
+                (triangular_prism_estimate(&surface, &dyadic_scales(&surface, 1)), 0.1),
+            ];
+            for (estimate, tolerance) in estimates {
+                assert!((estimate.hurst - hurst).abs() < tolerance, "H = {} estimated as {}", hurst, estimate.hurst);
+                assert!((estimate.dimension - (3.0 - hurst)).abs() < tolerance);
+                assert!(estimate.fit.r_squared > 0.99);
+            }
+        }
+    }
 
-    let denominator = n * sum_xx - sum_x * sum_x;
-    let numerator = n * sum_xy - sum_x * sum_y;
+    #[test]
+    fn differential_box_counting_finds_the_dimension_of_smooth_fractional_brownian_surfaces() {
+        // Box counting reads low on rough surfaces, so D = 3 - H only holds closely for H near 1. Rougher
+        // surfaces still come out rougher.
+        let smooth = power_law_surface(256, 3.6, 7);
+        let smooth = differential_box_counting(&smooth, &dyadic_scales(&smooth, 1));
+        assert!((smooth.dimension - 2.2).abs() < 0.1, "D = 2.2 estimated as {}", smooth.dimension);
+        assert!(smooth.fit.r_squared > 0.999);
 
-    numerator / denominator
-}
\ No newline at end of file
+        let rough = power_law_surface(256, 3.0, 7);
+        let rough = differential_box_counting(&rough, &dyadic_scales(&rough, 1));
+        assert!(rough.dimension > smooth.dimension + 0.05);
+        assert!(rough.fit.r_squared > 0.999);
+    }
+}
@@ -0,0 +1,40 @@
+use std::fs;
+
+// Loading heightmaps from files, e.g. real DEMs to compare generated terrain against.
+
+// Loads a heightmap from a file:
+// - .png: 8 or 16 bit grayscale, black is 0.0 and white is height_scale.
+// - anything else: text with one row of the heightmap per line, the heights separated by commas or
+//   whitespace. Empty lines and lines starting with # are skipped.
+pub fn load_heightmap(path: &str, height_scale: f32) -> Result<Vec<Vec<f32>>, String> {
+    let heightmap = if path.to_lowercase().ends_with(".png") {
+        let image = image::open(path).map_err(|error| format!("Couldn't read {}: {}", path, error))?.into_luma16();
+        image.rows().map(|row|
+            row.map(|pixel| pixel.0[0] as f32 / u16::MAX as f32 * height_scale).collect()
+        ).collect::<Vec<Vec<f32>>>()
+    } else {
+        let text = fs::read_to_string(path).map_err(|error| format!("Couldn't read {}: {}", path, error))?;
+        let mut heightmap = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let row = line.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<f32>().map_err(|_| format!("{}:{}: invalid height {}", path, number + 1, value)))
+                .collect::<Result<Vec<f32>, String>>()?;
+            heightmap.push(row);
+        }
+        heightmap
+    };
+
+    if heightmap.is_empty() || heightmap[0].is_empty() {
+        return Err(format!("{} has no heights", path));
+    }
+    if heightmap.iter().any(|row| row.len() != heightmap[0].len()) {
+        return Err(format!("{} has rows of different lengths", path));
+    }
+
+    Ok(heightmap)
+}
@@ -0,0 +1,236 @@
+use crate::convolution::{convolve, BoundaryMode};
+use std::fs::File;
+use std::io::BufWriter;
+
+// Landform classification: where the ridges, valleys, peaks, pits, slopes and flats of a heightmap are.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Landform {
+    Flat,
+    Peak,
+    Ridge,
+    Shoulder,
+    Spur,
+    Slope,
+    Hollow,
+    Footslope,
+    Valley,
+    Pit,
+}
+
+pub const LANDFORMS: [Landform; 10] = [
+    Landform::Flat,
+    Landform::Peak,
+    Landform::Ridge,
+    Landform::Shoulder,
+    Landform::Spur,
+    Landform::Slope,
+    Landform::Hollow,
+    Landform::Footslope,
+    Landform::Valley,
+    Landform::Pit,
+];
+
+impl Landform {
+    // Index of the landform in LANDFORMS, which is also its palette index in exported PNGs.
+    pub fn index(&self) -> usize {
+        LANDFORMS.iter().position(|landform| landform == self).unwrap()
+    }
+
+    // Color of the landform, the palette of the original geomorphon paper.
+    pub fn color(&self) -> [u8; 3] {
+        match self {
+            Landform::Flat => [220, 220, 220],
+            Landform::Peak => [56, 0, 0],
+            Landform::Ridge => [200, 0, 0],
+            Landform::Shoulder => [255, 80, 20],
+            Landform::Spur => [250, 210, 60],
+            Landform::Slope => [255, 255, 60],
+            Landform::Hollow => [180, 230, 20],
+            Landform::Footslope => [60, 250, 150],
+            Landform::Valley => [0, 0, 255],
+            Landform::Pit => [0, 0, 56],
+        }
+    }
+}
+
+// Topographic position index at each radius: the height of every sample minus the mean height of the
+// samples within radius of it. Positive on ridges and hilltops, negative in valleys, close to 0 on flats
+// and even slopes. Small radii pick out small features, large radii the large landforms they sit on.
+pub fn topographic_position_index(heightmap: &[Vec<f32>], radii: &[usize]) -> Vec<Vec<Vec<f32>>> {
+    radii.iter().map(|&radius| {
+        let neighbourhood_mean = convolve(heightmap, &annulus_kernel(radius), BoundaryMode::Mirror);
+        heightmap.iter().zip(neighbourhood_mean.iter()).map(|(row, mean_row)|
+            row.iter().zip(mean_row.iter()).map(|(height, mean)| height - mean).collect()
+        ).collect()
+    }).collect()
+}
+
+// Normalized kernel averaging the samples within radius, without the center one.
+fn annulus_kernel(radius: usize) -> Vec<Vec<f32>> {
+    let radius = radius.max(1) as isize;
+    let mut kernel: Vec<Vec<f32>> = (-radius..=radius).map(|y|
+        (-radius..=radius).map(|x| {
+            let distance_squared = x * x + y * y;
+            if distance_squared > 0 && distance_squared <= radius * radius { 1.0 } else { 0.0 }
+        }).collect()
+    ).collect();
+
+    let total: f32 = kernel.iter().flatten().sum();
+    for weight in kernel.iter_mut().flatten() {
+        *weight /= total;
+    }
+    kernel
+}
+
+#[derive(Clone, Copy)]
+pub struct GeomorphonParams {
+    // How far each sample looks along the 8 directions, in samples. Roughly the size of the largest
+    // landforms that are recognised.
+    pub lookup_distance: usize,
+    // Smallest angle in degrees between the horizon and a line of sight for the neighbour to count as
+    // higher or lower rather than level.
+    pub flatness_threshold: f32,
+    // Distance between neighbouring samples, in the units of the heights.
+    pub cell_size: f32,
+}
+
+impl Default for GeomorphonParams {
+    fn default() -> Self {
+        GeomorphonParams {
+            lookup_distance: 20,
+            flatness_threshold: 1.0,
+            cell_size: 1.0,
+        }
+    }
+}
+
+// Geomorphons (Jasiewicz and Stepinski). Every sample looks along the 8 compass directions up to the
+// lookup distance, and each direction counts as higher, lower or level depending on whether the terrain
+// rises above the sample or drops below it more. The number of higher and lower directions gives the
+// landform: all lower is a peak, all higher a pit, and so on.
+pub fn geomorphons(heightmap: &[Vec<f32>], params: &GeomorphonParams) -> Vec<Vec<Landform>> {
+    let height = heightmap.len() as isize;
+    let width = heightmap[0].len() as isize;
+    let directions = [(-1, 0), (-1, 1), (0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1)];
+    let threshold = params.flatness_threshold.to_radians();
+
+    (0..height).map(|i|
+        (0..width).map(|j| {
+            let center = heightmap[i as usize][j as usize];
+            let (mut higher, mut lower) = (0, 0);
+
+            for &(di, dj) in &directions {
+                let step = params.cell_size * if di != 0 && dj != 0 { 2.0_f32.sqrt() } else { 1.0 };
+                // Highest and lowest elevation angles along the line of sight.
+                let (mut highest, mut lowest) = (f32::NEG_INFINITY, f32::INFINITY);
+                for k in 1..=params.lookup_distance as isize {
+                    let (y, x) = (i + di * k, j + dj * k);
+                    if y < 0 || y >= height || x < 0 || x >= width {
+                        break;
+                    }
+                    let angle = ((heightmap[y as usize][x as usize] - center) / (step * k as f32)).atan();
+                    highest = highest.max(angle);
+                    lowest = lowest.min(angle);
+                }
+                if highest == f32::NEG_INFINITY {
+                    // Nothing to look at past the edge, which counts as level.
+                    continue;
+                }
+
+                // The nadir angle minus the zenith angle of the line of sight.
+                let difference = highest + lowest;
+                if difference > threshold {
+                    higher += 1;
+                } else if difference < -threshold {
+                    lower += 1;
+                }
+            }
+
+            GEOMORPHON_FORMS[lower][higher]
+        }).collect()
+    ).collect()
+}
+
+// Landform by number of lower (rows) and higher (columns) directions. Only the entries with 8 directions
+// or fewer in total are ever read.
+const GEOMORPHON_FORMS: [[Landform; 9]; 9] = {
+    use Landform::*;
+    [
+        [Flat, Flat, Flat, Footslope, Footslope, Valley, Valley, Valley, Pit],
+        [Flat, Flat, Footslope, Footslope, Footslope, Valley, Valley, Valley, Pit],
+        [Flat, Shoulder, Slope, Slope, Hollow, Hollow, Valley, Valley, Pit],
+        [Shoulder, Shoulder, Slope, Slope, Slope, Hollow, Valley, Valley, Pit],
+        [Shoulder, Shoulder, Spur, Slope, Slope, Hollow, Valley, Valley, Pit],
+        [Ridge, Ridge, Spur, Spur, Slope, Hollow, Valley, Valley, Pit],
+        [Ridge, Ridge, Ridge, Spur, Slope, Hollow, Valley, Valley, Pit],
+        [Ridge, Ridge, Ridge, Spur, Slope, Hollow, Valley, Valley, Pit],
+        [Peak, Peak, Peak, Peak, Peak, Peak, Peak, Peak, Peak],
+    ]
+};
+
+// Saves a landform map as an indexed PNG, with the landform colors as its palette in the order of
+// LANDFORMS, so the class of every pixel can be read back from its index.
+pub fn save_landform_png(landforms: &[Vec<Landform>], path: &str) -> std::io::Result<()> {
+    let height = landforms.len() as u32;
+    let width = landforms[0].len() as u32;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(LANDFORMS.iter().flat_map(|landform| landform.color()).collect::<Vec<u8>>());
+
+    let indices: Vec<u8> = landforms.iter().flatten().map(|landform| landform.index() as u8).collect();
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&indices)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::plane;
+
+    // Cone of height a rising from the corners of a 31 x 31 heightmap to its center, a pit for negative a.
+    fn cone(a: f32) -> Vec<Vec<f32>> {
+        (0..31).map(|i| (0..31).map(|j| {
+            let distance = ((i as f32 - 15.0).powi(2) + (j as f32 - 15.0).powi(2)).sqrt();
+            a * (1.0 - distance / 15.0)
+        }).collect()).collect()
+    }
+
+    #[test]
+    fn geomorphons_find_peaks_pits_and_slopes() {
+        let params = GeomorphonParams { lookup_distance: 10, ..GeomorphonParams::default() };
+        assert_eq!(geomorphons(&cone(20.0), &params)[15][15], Landform::Peak);
+        assert_eq!(geomorphons(&cone(-20.0), &params)[15][15], Landform::Pit);
+
+        // Away from the edges, which count as level.
+        let slope = geomorphons(&plane(31, 0.25, 0.5), &params);
+        let flat = geomorphons(&plane(31, 0.0, 0.0), &params);
+        for i in 10..21 {
+            for j in 10..21 {
+                assert_eq!(slope[i][j], Landform::Slope);
+                assert_eq!(flat[i][j], Landform::Flat);
+            }
+        }
+    }
+
+    #[test]
+    fn topographic_position_index_rises_on_hilltops() {
+        let radii = [2, 5];
+        for (radius, tpi) in radii.iter().zip(topographic_position_index(&cone(20.0), &radii)) {
+            assert!(tpi[15][15] > 0.0, "TPI {} at the apex for radius {}", tpi[15][15], radius);
+        }
+        for (radius, tpi) in radii.iter().zip(topographic_position_index(&cone(-20.0), &radii)) {
+            assert!(tpi[15][15] < 0.0, "TPI {} at the bottom for radius {}", tpi[15][15], radius);
+        }
+
+        // An even slope is as high as its surroundings on average, further than the radius from the edges.
+        for (&radius, tpi) in radii.iter().zip(topographic_position_index(&plane(31, 0.25, 0.5), &radii)) {
+            for row in &tpi[radius..31 - radius] {
+                assert!(row[radius..31 - radius].iter().all(|value| value.abs() < 1e-4));
+            }
+        }
+    }
+}
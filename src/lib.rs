@@ -0,0 +1,31 @@
+// Terrain generators and analysis tools, shared by the viewer and command line in main.rs and by the
+// benchmarks. Only mesh.rs and rasterizer.rs use bevy, for its vector math.
+
+pub mod terrain;
+pub mod fft_utils;
+pub mod noise;
+pub mod fractal_analysis;
+pub mod chunk;
+pub mod constraints;
+pub mod amplification;
+pub mod resample;
+pub mod filters;
+pub mod convolution;
+pub mod random_fields;
+pub mod multifractal;
+pub mod stats;
+pub mod correlation;
+pub mod comparison;
+pub mod derivatives;
+pub mod landforms;
+pub mod peaks;
+pub mod drainage;
+pub mod contours;
+pub mod shading;
+pub mod mesh;
+pub mod rasterizer;
+pub mod heightmap_io;
+pub mod cli;
+
+#[cfg(test)]
+mod fixtures;
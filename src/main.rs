@@ -10,11 +10,26 @@
 //It uses the Bevy engine to render the heightmaps with simple lighting and materials. Therefore, this
 //file contains functions related to the bevy engine. Most of it is boiler plate from Bevy documentation.
 
-//You may find terrain generators, utilities and noise generators in the files:
+//You may find terrain generators, utilities and noise generators in the library, lib.rs, and its files:
 // - terrain.rs
 // - noise.rs
 // - fft_utils.rs
 // - fractal_analysis.rs
+// - multifractal.rs
+// - stats.rs
+// - correlation.rs
+// - comparison.rs
+// - derivatives.rs
+// - landforms.rs
+// - peaks.rs
+// - drainage.rs
+// - contours.rs
+// - shading.rs
+// - mesh.rs
+// - rasterizer.rs
+
+//Run with a command, e.g. `ftt-terrain multifractal --generator fft`, it runs the analysis tools in
+//cli.rs from the command line instead of opening the viewer.
 
 
 use bevy::prelude::*;
@@ -27,27 +42,81 @@ use bevy::render::{
     render_resource::PrimitiveTopology,
     camera::{Exposure, PhysicalCameraParameters},
 };
+use bevy::utils::HashMap;
 use std::f32::consts::PI;
 
-use crate::noise::noise_terrain;
-use crate::noise::NoiseType;
-use crate::fractal_analysis::calculate_fractal_dimension;
+use ftt_terrain::noise::noise_terrain;
+use ftt_terrain::noise::NoiseType;
+use ftt_terrain::fractal_analysis::{
+    calculate_fractal_dimension, detrended_fluctuation_analysis, dyadic_scales, power_spectrum,
+    triangular_prism_estimate, variogram_estimate,
+};
+use ftt_terrain::chunk::{generate_chunk, ChunkGenerator};
+use ftt_terrain::filters::Anisotropy;
+use ftt_terrain::stats::terrain_stats;
+use ftt_terrain::landforms::{geomorphons, GeomorphonParams};
+use ftt_terrain::peaks::find_peaks;
+use ftt_terrain::mesh::heightmap_mesh;
 
-mod terrain;
-mod fft_utils;
-mod noise;
-mod fractal_analysis;
+use ftt_terrain::{cli, noise, terrain};
 
 // Define a "marker" component to mark the custom mesh. Marker components are often used in Bevy for
 // filtering entities in queries with With, they're usually not queried directly since they don't contain information within them.
 #[derive(Component)]
 struct CustomUV;
 
+// Marker for the text showing the statistics of the current terrain.
+#[derive(Component)]
+struct StatsOverlay;
+
+// Marker for the spheres marking the most prominent peaks of the current terrain.
+#[derive(Component)]
+struct PeakMarker;
+
+// At most MARKED_PEAKS peaks are marked, the ones rising at least MARKED_PROMINENCE of the height range
+// above their surroundings.
+const MARKED_PEAKS: usize = 10;
+const MARKED_PROMINENCE: f32 = 0.05;
+
+// Marker for the meshes of a streamed chunk.
+#[derive(Component)]
+struct TerrainChunk;
+
+// Chunks are CHUNK_SIZE cells wide, the ones within CHUNK_RADIUS chunks of the point the camera
+// looks at are kept loaded and at most CHUNKS_PER_FRAME new ones are generated per frame.
+const CHUNK_SIZE: usize = 64;
+const CHUNK_RADIUS: i32 = 3;
+const CHUNKS_PER_FRAME: usize = 2;
+const CAMERA_PAN_SPEED: f32 = 60.0;
+
+// State of the streamed chunk world. When a generator is set, chunks are streamed around the camera.
+#[derive(Resource)]
+struct ChunkWorld {
+    seed: u32,
+    generator: Option<ChunkGenerator>,
+    loaded: HashMap<(i32, i32), Entity>,
+}
+
 fn main() {
+    // With arguments the command line analysis tools run instead of the viewer.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(message) = cli::run(&args) {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .add_plugins(DefaultPlugins)
+        .insert_resource(ChunkWorld {
+            seed: rand::random(),
+            generator: None,
+            loaded: HashMap::new(),
+        })
         .add_systems(Startup, setup)
-        .add_systems(Update, input_handler)
+        .add_systems(Update, (input_handler, chunk_input_handler, stream_chunks, landform_overlay_handler, peak_marker_handler))
         .run();
 }
 
@@ -113,7 +182,7 @@ fn setup(
     // Text to describe the controls.
     commands.spawn(
         TextBundle::from_section(
-            "Controls:\n X/Y/Z: Rotate\n M: Generate Midpoint Displacement \n F: Generate FFT \n P: Generate Perlin Noise \n S: Generate Simplex Noise \n W: Generate Worley Noise",
+            "Controls:\n X/Y/Z: Rotate\n M: Generate Midpoint Displacement \n H: Generate Hurst Midpoint Displacement \n F: Generate FFT \n A: Generate Anisotropic FFT \n P: Generate Perlin Noise \n S: Generate Simplex Noise \n W: Generate Worley Noise \n C: Stream Perlin Noise Chunks \n V: Stream Midpoint Displacement Chunks \n L: Color Landforms \n K: Mark Peaks \n Arrows: Move Camera",
             TextStyle {
                 font_size: 20.0,
                 ..default()
@@ -126,6 +195,33 @@ fn setup(
             ..default()
         }),
     );
+
+    // Statistics of the current terrain.
+    commands.spawn((
+        TextBundle::from_section(
+            stats_text(&heightmap),
+            TextStyle {
+                font_size: 20.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            right: Val::Px(12.0),
+            ..default()
+        }),
+        StatsOverlay,
+    ));
+}
+
+// Summary of terrain_stats for the overlay.
+fn stats_text(heightmap: &[Vec<f32>]) -> String {
+    let stats = terrain_stats(heightmap);
+    format!(
+        "Sa: {:.2}\n Sq: {:.2}\n Ssk: {:.2}\n Sku: {:.2}\n Sz: {:.2}\n Sdr: {:.3}\n Sal: {:.1}\n Str: {:.2}\n Hypsometric integral: {:.2}",
+        stats.sa, stats.sq, stats.ssk, stats.sku, stats.sz, stats.sdr, stats.sal, stats.str, stats.hypsometric_integral
+    )
 }
 
 // System to receive input from the user,
@@ -136,6 +232,7 @@ fn input_handler(
     mut entity_query: Query<Entity, With<CustomUV>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut commands: Commands,
+    mut overlay_query: Query<&mut Text, With<StatsOverlay>>,
     time: Res<Time>,
 ) {
     let n = 8;
@@ -168,6 +265,51 @@ fn input_handler(
         let heightmap = terrain::midpoint_displacement(n, 0.75, 0.50, 45.0);
         let size = 2_usize.pow(n);
 
+        for mut text in &mut overlay_query {
+            text.sections[0].value = stats_text(&heightmap);
+        }
+
+        let terrain_mesh_handle: Handle<Mesh> = meshes.add(create_mesh(&heightmap));
+
+        // Render the mesh with the custom texture using a PbrBundle, add the marker.
+        commands.spawn((
+            PbrBundle {
+                mesh: terrain_mesh_handle,
+                material: materials.add(StandardMaterial {
+                    ..default()
+                }),
+                ..default()
+            },
+            CustomUV,
+        ));
+    }
+
+    if keyboard_input.just_pressed(KeyCode::KeyH) {
+        for entity in entity_query.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        let hurst = 0.8;
+        let heightmap = terrain::midpoint_displacement_hurst(n, hurst, 20.0, true);
+
+        // The surface should have a fractal dimension close to 3 - H.
+        info!(
+            "Fractal dimension: {}, expected about {}",
+            calculate_fractal_dimension(&heightmap),
+            3.0 - hurst
+        );
+        info!(
+            "Hurst exponent: variogram {}, DFA {}, triangular prism {}, expected {}",
+            variogram_estimate(&heightmap, &dyadic_scales(&heightmap, 1)).hurst,
+            detrended_fluctuation_analysis(&heightmap, &dyadic_scales(&heightmap, 4)).hurst,
+            triangular_prism_estimate(&heightmap, &dyadic_scales(&heightmap, 1)).hurst,
+            hurst
+        );
+
+        for mut text in &mut overlay_query {
+            text.sections[0].value = stats_text(&heightmap);
+        }
+
         let terrain_mesh_handle: Handle<Mesh> = meshes.add(create_mesh(&heightmap));
 
         // Render the mesh with the custom texture using a PbrBundle, add the marker.
@@ -191,6 +333,45 @@ fn input_handler(
         let heightmap = terrain::fft_terrain(n);
         let size = 2_usize.pow(n);
 
+        let spectrum = power_spectrum(&heightmap);
+        info!("Spectral exponent: {} (R² {})", spectrum.beta, spectrum.fit.r_squared);
+
+        for mut text in &mut overlay_query {
+            text.sections[0].value = stats_text(&heightmap);
+        }
+
+        let terrain_mesh_handle: Handle<Mesh> = meshes.add(create_mesh(&heightmap));
+
+        // Render the mesh with the custom texture using a PbrBundle, add the marker.
+        commands.spawn((
+            PbrBundle {
+                mesh: terrain_mesh_handle,
+                material: materials.add(StandardMaterial {
+                    ..default()
+                }),
+                ..default()
+            },
+            CustomUV,
+        ));
+    }
+
+    if keyboard_input.just_pressed(KeyCode::KeyA) {
+        for entity in entity_query.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        // Ridges running diagonally, four times longer than they are wide.
+        let anisotropy = Anisotropy {
+            angle: PI / 4.0,
+            ratio: 4.0,
+            spread: Some(0.6),
+        };
+        let heightmap = terrain::fft_terrain_anisotropic(n, &anisotropy);
+
+        for mut text in &mut overlay_query {
+            text.sections[0].value = stats_text(&heightmap);
+        }
+
         let terrain_mesh_handle: Handle<Mesh> = meshes.add(create_mesh(&heightmap));
 
         // Render the mesh with the custom texture using a PbrBundle, add the marker.
@@ -215,6 +396,10 @@ fn input_handler(
         let heightmap = noise::noise_terrain(n, noiseType);
         let size = 2_usize.pow(n);
 
+        for mut text in &mut overlay_query {
+            text.sections[0].value = stats_text(&heightmap);
+        }
+
         let terrain_mesh_handle: Handle<Mesh> = meshes.add(create_mesh(&heightmap));
 
         // Render the mesh with the custom texture using a PbrBundle, add the marker.
@@ -239,6 +424,10 @@ fn input_handler(
         let heightmap = noise::noise_terrain(n, noiseType);
         let size = 2_usize.pow(n);
 
+        for mut text in &mut overlay_query {
+            text.sections[0].value = stats_text(&heightmap);
+        }
+
         let terrain_mesh_handle: Handle<Mesh> = meshes.add(create_mesh(&heightmap));
 
         // Render the mesh with the custom texture using a PbrBundle, add the marker.
@@ -263,6 +452,10 @@ fn input_handler(
         let heightmap = noise::noise_terrain(n, noiseType);
         let size = 2_usize.pow(n);
 
+        for mut text in &mut overlay_query {
+            text.sections[0].value = stats_text(&heightmap);
+        }
+
         let terrain_mesh_handle: Handle<Mesh> = meshes.add(create_mesh(&heightmap));
 
         // Render the mesh with the custom texture using a PbrBundle, add the marker.
@@ -279,72 +472,243 @@ fn input_handler(
     }
 }
 
-// Create a mesh that bevy can render using a heightmap
-#[rustfmt::skip]
-fn create_mesh(heightmap: &Vec<Vec<f32>>) -> Mesh {
-   let width = heightmap[0].len();
-    let height = heightmap.len();
-
-    // Calculate the center of the mesh
-    let center_x = width as f32 / 2.0;
-    let center_y = 0.0; 
-    let center_z = height as f32 / 2.0;
-
-    let mut positions: Vec<[f32; 3]> = Vec::new();
-    let mut normals: Vec<[f32; 3]> = vec![[0.0; 3]; width * height]; 
-    let mut indices: Vec<u32> = Vec::new();
-
-    for y in 0..height {
-        for x in 0..width {
-            let adjusted_x = x as f32 - center_x;
-            let adjusted_y = heightmap[y][x];
-            let adjusted_z = y as f32 - center_z;
-
-            positions.push([adjusted_x, adjusted_y, adjusted_z]);
+// System to toggle coloring the current terrain by its geomorphon landforms.
+fn landform_overlay_handler(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mesh_query: Query<&Handle<Mesh>, With<CustomUV>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::KeyL) {
+        return;
+    }
+
+    for handle in mesh_query.iter() {
+        let Some(mesh) = meshes.get_mut(handle) else {
+            continue;
+        };
+        if mesh.contains_attribute(Mesh::ATTRIBUTE_COLOR) {
+            mesh.remove_attribute(Mesh::ATTRIBUTE_COLOR);
+            continue;
         }
+        let Some(heightmap) = mesh_heightmap(mesh) else {
+            continue;
+        };
+
+        let colors: Vec<[f32; 4]> = geomorphons(&heightmap, &GeomorphonParams::default()).iter().flatten().map(|landform| {
+            let [r, g, b] = landform.color();
+            [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0]
+        }).collect();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
     }
+}
 
-    for y in 0..height - 1 {
-        for x in 0..width - 1 {
-            let i = y * width + x;
-            let top_left = i;
-            let top_right = i + 1;
-            let bottom_left = i + width;
-            let bottom_right = i + width + 1;
-
-            indices.extend_from_slice(&[top_left as u32, bottom_left as u32, top_right as u32]);
-            indices.extend_from_slice(&[top_right as u32, bottom_left as u32, bottom_right as u32]);
-
-            // Calculate normals for the two triangles
-            let tri_1 = [positions[top_left], positions[bottom_left], positions[top_right]];
-            let tri_2 = [positions[top_right], positions[bottom_left], positions[bottom_right]];
-
-            let normal_1 = calc_normal(tri_1[0], tri_1[1], tri_1[2]);
-            let normal_2 = calc_normal(tri_2[0], tri_2[1], tri_2[2]);
-
-            // Since a vertex can belong to multiple triangles, average the normals
-            normals[top_left] = avg_normal(normals[top_left], normal_1);
-            normals[bottom_left] = avg_normal(normals[bottom_left], normal_1);
-            normals[top_right] = avg_normal(avg_normal(normals[top_right], normal_1), normal_2);
-            normals[bottom_right] = avg_normal(normals[bottom_right], normal_2);
+// System to toggle markers on the most prominent peaks of the current terrain. The markers are children
+// of the terrain so they rotate with it, and go away when it's replaced.
+fn peak_marker_handler(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    terrain_query: Query<(Entity, &Handle<Mesh>), With<CustomUV>>,
+    marker_query: Query<Entity, With<PeakMarker>>,
+    mut removed_terrain: RemovedComponents<CustomUV>,
+    mut commands: Commands,
+) {
+    let terrain_removed = removed_terrain.read().next().is_some();
+    if terrain_removed || (keyboard_input.just_pressed(KeyCode::KeyK) && !marker_query.is_empty()) {
+        for entity in marker_query.iter() {
+            commands.entity(entity).despawn();
         }
+        return;
+    }
+    if !keyboard_input.just_pressed(KeyCode::KeyK) {
+        return;
     }
 
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, VertexAttributeValues::Float32x3(positions));
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::Float32x3(normals));
-    mesh.insert_indices(bevy::render::mesh::Indices::U32(indices));
+    for (terrain, handle) in terrain_query.iter() {
+        let Some(heightmap) = meshes.get(handle).and_then(mesh_heightmap) else {
+            continue;
+        };
+        let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+        let highest = heightmap.iter().flatten().fold(f32::NEG_INFINITY, |highest, &value| highest.max(value));
+        let peaks = find_peaks(&heightmap, MARKED_PROMINENCE * (highest - lowest));
+
+        let marker_mesh = meshes.add(Sphere::new(1.0).mesh().uv(16, 8));
+        let marker_material = materials.add(StandardMaterial {
+            base_color: Color::RED,
+            unlit: true,
+            ..default()
+        });
+        // Same layout as create_mesh, centered on the origin.
+        let (width, height) = (heightmap[0].len() as f32, heightmap.len() as f32);
+        commands.entity(terrain).with_children(|parent| {
+            for peak in peaks.iter().take(MARKED_PEAKS) {
+                parent.spawn((
+                    PbrBundle {
+                        mesh: marker_mesh.clone(),
+                        material: marker_material.clone(),
+                        transform: Transform::from_xyz(peak.column as f32 - width / 2.0, peak.height + 1.0, peak.row as f32 - height / 2.0),
+                        ..default()
+                    },
+                    PeakMarker,
+                ));
+            }
+        });
+    }
+}
 
-    mesh
+// Heightmap back from the vertices of a mesh made by create_mesh, which are laid out row by row.
+fn mesh_heightmap(mesh: &Mesh) -> Option<Vec<Vec<f32>>> {
+    let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
+        return None;
+    };
+    let first_z = positions.first()?[2];
+    let width = positions.iter().take_while(|position| position[2] == first_z).count();
+    Some(positions.chunks(width).map(|row| row.iter().map(|position| position[1]).collect()).collect())
 }
 
-fn calc_normal(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3]) -> [f32; 3] {
-    let v0 = Vec3::from(p1) - Vec3::from(p0);
-    let v1 = Vec3::from(p2) - Vec3::from(p0);
-    let normal = v0.cross(v1).normalize();
-    normal.into()
+// System to switch the streamed chunk world on and off and to move the camera around it.
+fn chunk_input_handler(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut chunk_world: ResMut<ChunkWorld>,
+    mut camera_query: Query<&mut Transform, With<Camera3d>>,
+    entity_query: Query<Entity, With<CustomUV>>,
+    mut overlay_query: Query<&mut Text, With<StatsOverlay>>,
+    mut commands: Commands,
+    time: Res<Time>,
+) {
+    let mut generator = None;
+    if keyboard_input.just_pressed(KeyCode::KeyC) {
+        generator = Some(ChunkGenerator::Noise(noise::NoiseType::Perlin));
+    }
+    if keyboard_input.just_pressed(KeyCode::KeyV) {
+        generator = Some(ChunkGenerator::MidpointDisplacement {
+            initial_roughness: 0.75,
+            roughness_factor: 0.50,
+            initial_max_height: 45.0,
+        });
+    }
+
+    if generator.is_some() {
+        for entity in entity_query.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        // The chunk world has no single heightmap to describe.
+        for mut text in &mut overlay_query {
+            text.sections[0].value.clear();
+        }
+
+        // Chunks of the previous world are replaced by the new one.
+        for (_, entity) in chunk_world.loaded.drain() {
+            commands.entity(entity).despawn();
+        }
+        chunk_world.generator = generator;
+    }
+
+    // Generating a single terrain leaves the chunk world.
+    if keyboard_input.any_just_pressed([KeyCode::KeyM, KeyCode::KeyH, KeyCode::KeyF, KeyCode::KeyA, KeyCode::KeyP, KeyCode::KeyW, KeyCode::KeyS]) {
+        chunk_world.generator = None;
+    }
+
+    let mut direction = Vec3::ZERO;
+    if keyboard_input.pressed(KeyCode::ArrowUp) {
+        direction.z -= 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::ArrowDown) {
+        direction.z += 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::ArrowLeft) {
+        direction.x -= 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::ArrowRight) {
+        direction.x += 1.0;
+    }
+    for mut transform in &mut camera_query {
+        transform.translation += direction * CAMERA_PAN_SPEED * time.delta_seconds();
+    }
+}
+
+// System that loads the chunks around the camera and unloads the ones that went out of range.
+fn stream_chunks(
+    mut chunk_world: ResMut<ChunkWorld>,
+    camera_query: Query<&Transform, With<Camera3d>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    let Some(generator) = chunk_world.generator else {
+        for (_, entity) in chunk_world.loaded.drain() {
+            commands.entity(entity).despawn();
+        }
+        return;
+    };
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+
+    // Stream around the point where the camera looks at the ground.
+    let forward = camera.forward();
+    let focus = if forward.y < 0.0 {
+        camera.translation + forward * (-camera.translation.y / forward.y)
+    } else {
+        camera.translation
+    };
+    let center_x = (focus.x / CHUNK_SIZE as f32).floor() as i32;
+    let center_y = (focus.z / CHUNK_SIZE as f32).floor() as i32;
+
+    // Unload chunks one chunk past the radius, so moving back and forth over a chunk border doesn't regenerate them.
+    chunk_world.loaded.retain(|&(cx, cy), entity| {
+        let keep = (cx - center_x).abs() <= CHUNK_RADIUS + 1 && (cy - center_y).abs() <= CHUNK_RADIUS + 1;
+        if !keep {
+            commands.entity(*entity).despawn();
+        }
+        keep
+    });
+
+    let mut missing: Vec<(i32, i32)> = Vec::new();
+    for cy in (center_y - CHUNK_RADIUS)..=(center_y + CHUNK_RADIUS) {
+        for cx in (center_x - CHUNK_RADIUS)..=(center_x + CHUNK_RADIUS) {
+            if !chunk_world.loaded.contains_key(&(cx, cy)) {
+                missing.push((cx, cy));
+            }
+        }
+    }
+    missing.sort_by_key(|&(cx, cy)| (cx - center_x).pow(2) + (cy - center_y).pow(2));
+
+    for (cx, cy) in missing.into_iter().take(CHUNKS_PER_FRAME) {
+        let heightmap = generate_chunk(chunk_world.seed, cx, cy, CHUNK_SIZE, generator);
+
+        // create_mesh centers the mesh on the heightmap, move it back to the chunk's place in the world.
+        let offset = (CHUNK_SIZE + 1) as f32 / 2.0;
+        let translation = Vec3::new(
+            (cx * CHUNK_SIZE as i32) as f32 + offset,
+            0.0,
+            (cy * CHUNK_SIZE as i32) as f32 + offset,
+        );
+
+        let entity = commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(create_mesh(&heightmap)),
+                material: materials.add(StandardMaterial {
+                    ..default()
+                }),
+                transform: Transform::from_translation(translation),
+                ..default()
+            },
+            TerrainChunk,
+        )).id();
+        chunk_world.loaded.insert((cx, cy), entity);
+    }
 }
 
-fn avg_normal(n1: [f32; 3], n2: [f32; 3]) -> [f32; 3] {
-    ((Vec3::from(n1) + Vec3::from(n2)) * 0.5).normalize().into()
-}
\ No newline at end of file
+// Create a mesh that bevy can render using a heightmap
+fn create_mesh(heightmap: &[Vec<f32>]) -> Mesh {
+    let data = heightmap_mesh(heightmap);
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, VertexAttributeValues::Float32x3(data.positions));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::Float32x3(data.normals));
+    mesh.insert_indices(bevy::render::mesh::Indices::U32(data.indices));
+
+    mesh
+}
@@ -0,0 +1,102 @@
+use bevy::math::Vec3;
+
+// Triangle mesh of a heightmap, the vertex data create_mesh hands to Bevy and the software rasterizer
+// draws without it.
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+// One vertex per sample, row by row, centered on the origin with x along the rows, z down the columns
+// and the heights as y. Two triangles per cell, counterclockwise seen from above.
+pub fn heightmap_mesh(heightmap: &[Vec<f32>]) -> MeshData {
+    let width = heightmap[0].len();
+    let height = heightmap.len();
+
+    // Calculate the center of the mesh
+    let center_x = width as f32 / 2.0;
+    let center_z = height as f32 / 2.0;
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = vec![[0.0; 3]; width * height];
+    let mut indices: Vec<u32> = Vec::new();
+
+    for (y, row) in heightmap.iter().enumerate() {
+        for (x, &adjusted_y) in row.iter().enumerate() {
+            let adjusted_x = x as f32 - center_x;
+            let adjusted_z = y as f32 - center_z;
+
+            positions.push([adjusted_x, adjusted_y, adjusted_z]);
+        }
+    }
+
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            let i = y * width + x;
+            let top_left = i;
+            let top_right = i + 1;
+            let bottom_left = i + width;
+            let bottom_right = i + width + 1;
+
+            indices.extend_from_slice(&[top_left as u32, bottom_left as u32, top_right as u32]);
+            indices.extend_from_slice(&[top_right as u32, bottom_left as u32, bottom_right as u32]);
+
+            // Calculate normals for the two triangles
+            let tri_1 = [positions[top_left], positions[bottom_left], positions[top_right]];
+            let tri_2 = [positions[top_right], positions[bottom_left], positions[bottom_right]];
+
+            let normal_1 = calc_normal(tri_1[0], tri_1[1], tri_1[2]);
+            let normal_2 = calc_normal(tri_2[0], tri_2[1], tri_2[2]);
+
+            // Since a vertex can belong to multiple triangles, average the normals
+            normals[top_left] = avg_normal(normals[top_left], normal_1);
+            normals[bottom_left] = avg_normal(normals[bottom_left], normal_1);
+            normals[top_right] = avg_normal(avg_normal(normals[top_right], normal_1), normal_2);
+            normals[bottom_right] = avg_normal(normals[bottom_right], normal_2);
+        }
+    }
+
+    MeshData {
+        positions,
+        normals,
+        indices,
+    }
+}
+
+fn calc_normal(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3]) -> [f32; 3] {
+    let v0 = Vec3::from(p1) - Vec3::from(p0);
+    let v1 = Vec3::from(p2) - Vec3::from(p0);
+    let normal = v0.cross(v1).normalize();
+    normal.into()
+}
+
+fn avg_normal(n1: [f32; 3], n2: [f32; 3]) -> [f32; 3] {
+    ((Vec3::from(n1) + Vec3::from(n2)) * 0.5).normalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangles_turn_counterclockwise_seen_from_above() {
+        let heightmap: Vec<Vec<f32>> = (0..4).map(|y| (0..5).map(|x| (x + y) as f32 * 0.1).collect()).collect();
+        let mesh = heightmap_mesh(&heightmap);
+
+        assert_eq!(mesh.positions.len(), 20);
+        assert_eq!(mesh.indices.len(), 3 * 2 * 4 * 3);
+        assert_eq!(mesh.positions[0], [-2.5, 0.0, -2.0]);
+        for triangle in mesh.indices.chunks_exact(3) {
+            let normal = calc_normal(
+                mesh.positions[triangle[0] as usize],
+                mesh.positions[triangle[1] as usize],
+                mesh.positions[triangle[2] as usize],
+            );
+            assert!(normal[1] > 0.9, "normal {:?}", normal);
+        }
+        for normal in &mesh.normals {
+            assert!((Vec3::from(*normal).length() - 1.0).abs() < 1e-5 && normal[1] > 0.9, "normal {:?}", normal);
+        }
+    }
+}
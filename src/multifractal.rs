@@ -0,0 +1,232 @@
+use crate::fractal_analysis::fit_line;
+
+// Multifractal and lacunarity analysis. A single fractal dimension describes terrain that is equally rough
+// everywhere, these describe how unevenly the roughness or the height is spread over the heightmap.
+
+// Quantity whose distribution over the heightmap is analysed.
+#[derive(Clone, Copy)]
+pub enum Measure {
+    // Height above the lowest sample.
+    Height,
+    // Absolute height difference to the next sample along the row and the column, so rugged areas weigh
+    // more than smooth ones whatever their height.
+    Gradient,
+}
+
+// Multifractal spectrum as data series, one entry per moment order q.
+pub struct MultifractalSpectrum {
+    pub q: Vec<f32>,
+    // Mass exponent, the sum of the box measures to the power q grows as box_size^tau(q).
+    pub tau: Vec<f32>,
+    // Generalized dimensions D(q) = tau(q) / (q - 1). D(0) is the box counting dimension of the support,
+    // D(1) the information dimension and D(2) the correlation dimension. A monofractal has the same D(q)
+    // for every q.
+    pub generalized_dimensions: Vec<f32>,
+    // Singularity strength, how fast the measure of a box shrinks with its size around the boxes that
+    // dominate at order q.
+    pub alpha: Vec<f32>,
+    // Dimension of the set of boxes with singularity strength alpha. The wider the range of alpha, the
+    // more heterogeneous the heightmap.
+    pub f_alpha: Vec<f32>,
+}
+
+// Estimates D(q) and f(alpha) with the moment method, covering the heightmap with boxes of each size and
+// fitting against the log of the box size. f(alpha) comes from the direct method of Chhabra and Jensen
+// rather than from the Legendre transform of tau, which is less noisy for negative q.
+pub fn multifractal_spectrum(heightmap: &[Vec<f32>], measure: Measure, qs: &[f32], box_sizes: &[usize]) -> MultifractalSpectrum {
+    let field = measure_field(heightmap, measure);
+    let height = field.len();
+    let width = field[0].len();
+    let side = width.min(height) as f64;
+
+    // Probability of each box, for every box size that fits.
+    let mut log_sizes = Vec::new();
+    let mut probabilities = Vec::new();
+    for &box_size in box_sizes {
+        if box_size == 0 || box_size > width || box_size > height {
+            continue;
+        }
+
+        let mut masses = Vec::new();
+        for i in (0..=height - box_size).step_by(box_size) {
+            for j in (0..=width - box_size).step_by(box_size) {
+                let mass: f64 = field[i..i + box_size].iter().map(|row| row[j..j + box_size].iter().sum::<f64>()).sum();
+                masses.push(mass);
+            }
+        }
+        let total: f64 = masses.iter().sum();
+        if total <= 0.0 {
+            continue;
+        }
+
+        // Boxes without any measure are outside of the support and don't take part at any q.
+        log_sizes.push((box_size as f64 / side).ln() as f32);
+        probabilities.push(masses.into_iter().filter(|&mass| mass > 0.0).map(|mass| mass / total).collect::<Vec<f64>>());
+    }
+
+    let mut spectrum = MultifractalSpectrum {
+        q: Vec::new(),
+        tau: Vec::new(),
+        generalized_dimensions: Vec::new(),
+        alpha: Vec::new(),
+        f_alpha: Vec::new(),
+    };
+
+    for &q in qs {
+        let q64 = q as f64;
+        let mut log_moments = Vec::new();
+        let mut alpha_sums = Vec::new();
+        let mut f_sums = Vec::new();
+        let mut entropies = Vec::new();
+
+        for box_probabilities in &probabilities {
+            let moment: f64 = box_probabilities.iter().map(|p| p.powf(q64)).sum();
+            log_moments.push(moment.ln() as f32);
+
+            // Measures weighted towards the boxes that dominate at order q.
+            let (mut alpha_sum, mut f_sum) = (0.0, 0.0);
+            for p in box_probabilities {
+                let weighted = p.powf(q64) / moment;
+                alpha_sum += weighted * p.ln();
+                f_sum += weighted * weighted.ln();
+            }
+            alpha_sums.push(alpha_sum as f32);
+            f_sums.push(f_sum as f32);
+            entropies.push(box_probabilities.iter().map(|p| p * p.ln()).sum::<f64>() as f32);
+        }
+
+        let tau = fit_line(&log_sizes, &log_moments).slope;
+        let dimension = if (q - 1.0).abs() < 1e-6 {
+            fit_line(&log_sizes, &entropies).slope
+        } else {
+            tau / (q - 1.0)
+        };
+
+        spectrum.q.push(q);
+        spectrum.tau.push(tau);
+        spectrum.generalized_dimensions.push(dimension);
+        spectrum.alpha.push(fit_line(&log_sizes, &alpha_sums).slope);
+        spectrum.f_alpha.push(fit_line(&log_sizes, &f_sums).slope);
+    }
+
+    spectrum
+}
+
+// Lacunarity curve as data series.
+pub struct LacunarityCurve {
+    pub box_sizes: Vec<usize>,
+    // Second moment of the box mass over its squared first moment, 1.0 for a perfectly even heightmap.
+    // Higher values mean larger gaps, and how fast it falls with the box size tells at which scale the
+    // heightmap starts to look homogeneous.
+    pub lacunarity: Vec<f32>,
+}
+
+// Gliding box lacunarity (Allain and Cloitre) of the heights above the lowest sample. A box of each size
+// slides one sample at a time over the heightmap and the mass under it is the sum of the heights.
+pub fn lacunarity(heightmap: &[Vec<f32>], box_sizes: &[usize]) -> LacunarityCurve {
+    let field = measure_field(heightmap, Measure::Height);
+    let height = field.len();
+    let width = field[0].len();
+
+    // Summed area table with a leading row and column of zeros, so any box sums in constant time.
+    let mut summed = vec![vec![0.0_f64; width + 1]; height + 1];
+    for (i, row) in field.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            summed[i + 1][j + 1] = value + summed[i][j + 1] + summed[i + 1][j] - summed[i][j];
+        }
+    }
+
+    let mut curve = LacunarityCurve {
+        box_sizes: Vec::new(),
+        lacunarity: Vec::new(),
+    };
+
+    for &box_size in box_sizes {
+        if box_size == 0 || box_size > width || box_size > height {
+            continue;
+        }
+
+        let (mut sum, mut sum_squares, mut boxes) = (0.0_f64, 0.0_f64, 0.0_f64);
+        for i in 0..=height - box_size {
+            for j in 0..=width - box_size {
+                let mass = summed[i + box_size][j + box_size] - summed[i][j + box_size] - summed[i + box_size][j] + summed[i][j];
+                sum += mass;
+                sum_squares += mass * mass;
+                boxes += 1.0;
+            }
+        }
+
+        let mean = sum / boxes;
+        let lacunarity = if mean > 0.0 { sum_squares / boxes / (mean * mean) } else { 1.0 };
+
+        curve.box_sizes.push(box_size);
+        curve.lacunarity.push(lacunarity as f32);
+    }
+
+    curve
+}
+
+// Non-negative field of the measure, one value per sample.
+fn measure_field(heightmap: &[Vec<f32>], measure: Measure) -> Vec<Vec<f64>> {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+
+    match measure {
+        Measure::Height => {
+            let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+            heightmap.iter().map(|row| row.iter().map(|&value| (value - lowest) as f64).collect()).collect()
+        }
+        Measure::Gradient => (0..height).map(|i|
+            (0..width).map(|j| {
+                let right = heightmap[i][(j + 1).min(width - 1)] - heightmap[i][j];
+                let down = heightmap[(i + 1).min(height - 1)][j] - heightmap[i][j];
+                (right.abs() + down.abs()) as f64
+            }).collect()
+        ).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{noise, plane};
+
+    #[test]
+    fn uniform_measures_are_monofractal() {
+        // The gradient of a plane rising along the rows is the same everywhere but in the last column,
+        // which boxes of even sizes never reach on a 65 x 65 heightmap.
+        let heightmap = plane(65, 1.0, 0.0);
+        let qs = [-2.0, 0.0, 1.0, 2.0, 4.0];
+        let spectrum = multifractal_spectrum(&heightmap, Measure::Gradient, &qs, &[2, 4, 8, 16, 32]);
+
+        assert_eq!(spectrum.q, qs);
+        for k in 0..qs.len() {
+            assert!((spectrum.generalized_dimensions[k] - 2.0).abs() < 1e-3, "D({}) = {}", qs[k], spectrum.generalized_dimensions[k]);
+            assert!((spectrum.alpha[k] - 2.0).abs() < 1e-3, "alpha({}) = {}", qs[k], spectrum.alpha[k]);
+            assert!((spectrum.f_alpha[k] - 2.0).abs() < 1e-3, "f({}) = {}", qs[k], spectrum.f_alpha[k]);
+        }
+    }
+
+    #[test]
+    fn uneven_measures_spread_the_spectrum() {
+        let spectrum = multifractal_spectrum(&noise(64, 64, 2), Measure::Height, &[-4.0, 4.0], &[1, 2, 4, 8, 16]);
+
+        // Sparse boxes dominate at negative q and dense ones at positive q.
+        assert!(spectrum.generalized_dimensions[0] > spectrum.generalized_dimensions[1]);
+        assert!(spectrum.alpha[0] > spectrum.alpha[1]);
+    }
+
+    #[test]
+    fn lacunarity_falls_towards_one_with_the_box_size() {
+        let constant = lacunarity(&vec![vec![3.0; 16]; 16], &[1, 4, 16]);
+        assert_eq!(constant.lacunarity, [1.0, 1.0, 1.0]);
+
+        let curve = lacunarity(&noise(64, 64, 4), &[1, 2, 4, 8, 16, 0, 100]);
+        assert_eq!(curve.box_sizes, [1, 2, 4, 8, 16]);
+        for pair in curve.lacunarity.windows(2) {
+            assert!(pair[0] > pair[1] && pair[1] > 1.0, "{:?}", curve.lacunarity);
+        }
+        // Uniform noise in 0..2 has a second moment of 4 / 3 against a squared mean of 1.
+        assert!((curve.lacunarity[0] - 4.0 / 3.0).abs() < 0.05, "{:?}", curve.lacunarity);
+    }
+}
@@ -2,10 +2,10 @@ use noise::{NoiseFn, Perlin, Simplex, Worley};
 extern crate rand;
 use rand::thread_rng;
 use rand::Rng;
-use rayon::prelude::*; 
-use std::sync::atomic::{Ordering};
-use atomic_float::AtomicF32;
+use rayon::prelude::*;
+use crate::constraints::{apply_constraints, Constraints};
 
+#[derive(Clone, Copy)]
 pub enum NoiseType {
     Perlin,
     Worley,
@@ -41,44 +41,62 @@ impl NoiseGenerator for Worley {
     }
 }
 
+// These parameters can be adjusted to change the noise characteristics.
+const BASE_SCALE: f64 = 0.005; // Smaller scale for larger terrains
+const OCTAVES: usize = 10; // More octaves for additional complexity
+const PERSISTENCE: f32 = 0.5;
+const LACUNARITY: f64 = 2.0;
+
+// Height the normalized noise is stretched to.
+const MAX_HEIGHT: f32 = 50.0;
+
+fn make_noise_generator(noise_type: NoiseType, seed: u32) -> Box<dyn NoiseGenerator> {
+    match noise_type {
+        NoiseType::Perlin => Box::new(Perlin::new(seed)),
+        NoiseType::Simplex => Box::new(Simplex::new(seed)),
+        NoiseType::Worley => Box::new(Worley::new(seed)),
+    }
+}
+
+// Sums the octaves of the noise at a point given in grid coordinates.
+fn fractal_noise(noise_generator: &dyn NoiseGenerator, x: f64, y: f64) -> f32 {
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    let mut noise_value = 0.0;
+
+    // Generate noise with multiple octaves
+    for _ in 0..OCTAVES {
+        noise_value += noise_generator.generate_noise([
+            x * BASE_SCALE * frequency,
+            y * BASE_SCALE * frequency,
+        ]) as f32 * amplitude;
+
+        amplitude *= PERSISTENCE;
+        frequency *= LACUNARITY;
+    }
+
+    noise_value
+}
+
 pub fn noise_terrain(n: u32, noise_type: NoiseType) -> Vec<Vec<f32>> {
     let mut rng = thread_rng();
+    noise_terrain_with_seed(n, noise_type, rng.gen_range(0..100))
+}
+
+pub fn noise_terrain_with_seed(n: u32, noise_type: NoiseType, seed: u32) -> Vec<Vec<f32>> {
     let size = 2usize.pow(n);
 
-    let noise_generator: Box<dyn NoiseGenerator> = match noise_type {
-        NoiseType::Perlin => Box::new(Perlin::new(rng.gen_range(0..100))),
-        NoiseType::Simplex => Box::new(Simplex::new(rng.gen_range(0..100))),
-        NoiseType::Worley => Box::new(Worley::new(rng.gen_range(0..100))),
-    };
+    let noise_generator = make_noise_generator(noise_type, seed);
 
     let mut noise_grid = Vec::with_capacity(size);
 
-    // These parameters can be adjusted to change the noise characteristics.
-    let base_scale = 0.005; // Smaller scale for larger terrains
-    let octaves = 10; // More octaves for additional complexity
-    let persistence = 0.5;
-    let lacunarity = 2.0;
-
     let mut max_value = f32::MIN;
     let mut min_value = f32::MAX;
 
     for y in 0..size {
         let mut row = Vec::with_capacity(size);
         for x in 0..size {
-            let mut amplitude = 1.0;
-            let mut frequency = 1.0;
-            let mut noise_value = 0.0;
-
-            // Generate noise with multiple octaves
-            for _ in 0..octaves {
-                noise_value += noise_generator.generate_noise([
-                    x as f64 * base_scale * frequency, 
-                    y as f64 * base_scale * frequency,
-                ]) as f32 * amplitude;
-
-                amplitude *= persistence;
-                frequency *= lacunarity;
-            }
+            let noise_value = fractal_noise(noise_generator.as_ref(), x as f64, y as f64);
 
             max_value = max_value.max(noise_value);
             min_value = min_value.min(noise_value);
@@ -89,87 +107,101 @@ pub fn noise_terrain(n: u32, noise_type: NoiseType) -> Vec<Vec<f32>> {
     }
 
     // Normalize and apply a curve to emphasize elevation changes
-    for y in 0..size {
-        for x in 0..size {
-            let normalized_value = (noise_grid[y][x] - min_value) / (max_value - min_value);
-            // Apply an exponential curve to make the terrain more varied.
-            noise_grid[y][x] = normalized_value * 50.0;
-        }
+    for height in noise_grid.iter_mut().flatten() {
+        let normalized_value = (*height - min_value) / (max_value - min_value);
+        // Apply an exponential curve to make the terrain more varied.
+        *height = normalized_value * MAX_HEIGHT;
     }
 
     noise_grid
 }
 
-/* pub fn noise_terrain_par(n: u32, noise_type: NoiseType) -> Vec<Vec<f32>> {
-    let mut rng = thread_rng();
-    let size = 2usize.pow(n);
-
-    let noise_generator: Box<dyn NoiseGenerator> = match noise_type {
-        NoiseType::Perlin => Box::new(Perlin::new(rng.gen_range(0..100))),
-        NoiseType::Simplex => Box::new(Simplex::new(rng.gen_range(0..100))),
-        NoiseType::Worley => Box::new(Worley::new(rng.gen_range(0..100))),
-    };
-
-    // These parameters can be adjusted to change the noise characteristics.
-    let base_scale = 0.005; // Smaller scale for larger terrains
-    let octaves = 10; // More octaves for additional complexity
-    let persistence = 0.5;
-    let lacunarity = 2.0;
-
-    let max_value = AtomicF32::new(f32::MIN);
-    let min_value = AtomicF32::new(f32::MAX);
+// Noise terrain that honours the constraints, the correction fades out over about correlation_length cells.
+pub fn noise_terrain_constrained(n: u32, noise_type: NoiseType, seed: u32, constraints: &Constraints, correlation_length: f32) -> Vec<Vec<f32>> {
+    let mut heightmap = noise_terrain_par_with_seed(n, noise_type, seed);
+    apply_constraints(&mut heightmap, constraints, correlation_length);
+    heightmap
+}
 
-    let input_grid: Vec<Vec<f32>> = vec![vec![0.0; size]; size];
+// Parallel version of noise_terrain.
+pub fn noise_terrain_par(n: u32, noise_type: NoiseType) -> Vec<Vec<f32>> {
+    let mut rng = thread_rng();
+    noise_terrain_par_with_seed(n, noise_type, rng.gen_range(0..100))
+}
 
-    let output_grid: Vec<Vec<f32>> = input_grid
-        .par_iter() // Use parallel iterator over rows
-        .enumerate() // Include row index
-        .map(|(y, _)| {
+// Parallel version of noise_terrain_with_seed, it produces exactly the same heightmap for the same seed.
+// Rows are generated in parallel into a flat buffer, each row reports its own min and max and those are
+// reduced into the min and max of the whole map before normalizing.
+pub fn noise_terrain_par_with_seed(n: u32, noise_type: NoiseType, seed: u32) -> Vec<Vec<f32>> {
+    let size = 2usize.pow(n);
+    let mut noise_buffer = vec![0.0_f32; size * size];
 
-            let mut row = vec![0.0_f32; size];
+    let (min_value, max_value) = noise_buffer
+        .par_chunks_mut(size)
+        .enumerate()
+        // Worley noise isn't Sync, so every worker builds its own generator from the seed.
+        .map_init(
+            || make_noise_generator(noise_type, seed),
+            |noise_generator, (y, row)| {
+                let mut min_value = f32::MAX;
+                let mut max_value = f32::MIN;
 
-            for x in 0..size {
-                let mut amplitude = 1.0;
-                let mut frequency = 1.0;
-                let mut noise_value = 0.0;
+                for (x, height) in row.iter_mut().enumerate() {
+                    let noise_value = fractal_noise(noise_generator.as_ref(), x as f64, y as f64);
 
-                // Generate noise with multiple octaves
-                for _ in 0..octaves {
-                    noise_value += noise_generator.generate_noise([
-                        x as f64 * base_scale * frequency, 
-                        y as f64 * base_scale * frequency,
-                    ]) as f32 * amplitude;
+                    max_value = max_value.max(noise_value);
+                    min_value = min_value.min(noise_value);
 
-                    amplitude *= persistence;
-                    frequency *= lacunarity;
+                    *height = noise_value;
                 }
 
-                let current_max = max_value.load(Ordering::Relaxed);
-                if noise_value > current_max {
-                    max_value.store(noise_value, Ordering::Relaxed);
-                }
+                (min_value, max_value)
+            },
+        )
+        .reduce(
+            || (f32::MAX, f32::MIN),
+            |(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)),
+        );
+
+    // Normalize the same way as the serial version.
+    noise_buffer.par_iter_mut().for_each(|height| {
+        let normalized_value = (*height - min_value) / (max_value - min_value);
+        *height = normalized_value * MAX_HEIGHT;
+    });
+
+    noise_buffer.par_chunks(size).map(|row| row.to_vec()).collect()
+}
 
-                let current_min = min_value.load(Ordering::Relaxed);
-                if noise_value < current_min {
-                    min_value.store(noise_value, Ordering::Relaxed);
-                }
+// Samples a square window of the infinite noise field whose top left corner sits at
+// (origin_x, origin_y) in world grid coordinates. The result can't be normalized by its own
+// min/max like noise_terrain does, since neighbouring windows would then disagree on their
+// shared border, so it is normalized against the largest value the octave sum can reach.
+pub fn noise_window(noise_type: NoiseType, seed: u32, origin_x: i64, origin_y: i64, samples: usize) -> Vec<Vec<f32>> {
+    let noise_generator = make_noise_generator(noise_type, seed);
+
+    let amplitude_sum: f32 = (0..OCTAVES).map(|octave| PERSISTENCE.powi(octave as i32)).sum();
+
+    (0..samples).map(|y|
+        (0..samples).map(|x| {
+            let noise_value = fractal_noise(
+                noise_generator.as_ref(),
+                (origin_x + x as i64) as f64,
+                (origin_y + y as i64) as f64,
+            );
+            let normalized_value = ((noise_value / amplitude_sum + 1.0) / 2.0).clamp(0.0, 1.0);
+            normalized_value * MAX_HEIGHT
+        }).collect()
+    ).collect()
+}
 
-                row[x] = noise_value;
-            }
-            
-            row
-        })
-        .collect(); // Collect all rows into a 2D vector
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Normalize and apply a curve to emphasize elevation changes
-    for y in 0..size {
-        for x in 0..size {
-            let normalized_value = (output_grid[y][x] - min_value) / (max_value - min_value);
-            // Apply an exponential curve to make the terrain more varied.
-            output_grid[y][x] = normalized_value * 50.0;
+    #[test]
+    fn parallel_matches_serial() {
+        for noise_type in [NoiseType::Perlin, NoiseType::Simplex, NoiseType::Worley] {
+            assert_eq!(noise_terrain_with_seed(6, noise_type, 7), noise_terrain_par_with_seed(6, noise_type, 7));
         }
     }
-
-    output_grid
 }
- */
\ No newline at end of file
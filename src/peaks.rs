@@ -0,0 +1,221 @@
+use serde::Serialize;
+
+// Peaks of a heightmap ranked by topographic prominence, for placing landmarks. Distances are in samples.
+//
+// Prominence comes from flooding the heightmap from the top down: the samples are added from the highest
+// to the lowest, and a sample with no higher neighbour starts a new island with itself as the summit.
+// Where a sample joins two islands, it's the key col of the lower summit, whose prominence is how far it
+// rises above that col. Both islands then belong to the higher summit. Since flat tops grow as one island,
+// a plateau counts as a single peak.
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Peak {
+    // Row and column of the summit.
+    pub row: usize,
+    pub column: usize,
+    pub height: f32,
+    // Height of the summit above its key col, the highest point it has to descend to on the way to higher
+    // ground. The highest peak has no key col and its prominence is its height above the lowest sample.
+    pub prominence: f32,
+    // Row and column of the key col.
+    pub key_col: Option<(usize, usize)>,
+    // Distance to the nearest higher sample, none when nothing on the heightmap is higher.
+    pub isolation: Option<f32>,
+}
+
+// Peaks with at least min_prominence, from the most to the least prominent. Peaks are only found within
+// the heightmap, so a summit on the edge may be the flank of a mountain that continues past it.
+pub fn find_peaks(heightmap: &[Vec<f32>], min_prominence: f32) -> Vec<Peak> {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let heights: Vec<f32> = heightmap.iter().flatten().copied().collect();
+    let lowest = heights.iter().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+
+    let mut order: Vec<usize> = (0..heights.len()).collect();
+    order.sort_by(|&a, &b| heights[b].total_cmp(&heights[a]));
+
+    // Union-find over the samples added so far, with the index of the summit of each island at its root.
+    const NOT_ADDED: usize = usize::MAX;
+    let mut parent = vec![NOT_ADDED; heights.len()];
+    let mut island_summit = vec![0; heights.len()];
+    let mut summits = Vec::new();
+    let mut key_cols = Vec::new();
+
+    for &cell in &order {
+        parent[cell] = cell;
+
+        let (i, j) = (cell / width, cell % width);
+        let mut islands: Vec<usize> = Vec::with_capacity(8);
+        for di in -1..=1_isize {
+            for dj in -1..=1_isize {
+                let (y, x) = (i as isize + di, j as isize + dj);
+                if (di == 0 && dj == 0) || y < 0 || y >= height as isize || x < 0 || x >= width as isize {
+                    continue;
+                }
+                let neighbour = y as usize * width + x as usize;
+                if parent[neighbour] != NOT_ADDED {
+                    let root = find(&mut parent, neighbour);
+                    if !islands.contains(&root) {
+                        islands.push(root);
+                    }
+                }
+            }
+        }
+
+        if islands.is_empty() {
+            island_summit[cell] = summits.len();
+            summits.push(cell);
+            key_cols.push(None);
+            continue;
+        }
+
+        // The island with the highest summit absorbs the others and the new sample.
+        let summit_height = |island: usize| heights[summits[island_summit[island]]];
+        let highest = *islands.iter().max_by(|&&a, &&b| summit_height(a).total_cmp(&summit_height(b))).unwrap();
+        for &island in &islands {
+            if island != highest {
+                key_cols[island_summit[island]] = Some(cell);
+                parent[island] = highest;
+            }
+        }
+        parent[cell] = highest;
+    }
+
+    let mut peaks: Vec<Peak> = summits.iter().zip(key_cols.iter()).filter_map(|(&summit, &key_col)| {
+        let base = key_col.map_or(lowest, |col| heights[col]);
+        let prominence = heights[summit] - base;
+        if prominence < min_prominence {
+            return None;
+        }
+        let (row, column) = (summit / width, summit % width);
+        Some(Peak {
+            row,
+            column,
+            height: heights[summit],
+            prominence,
+            key_col: key_col.map(|col| (col / width, col % width)),
+            isolation: isolation(heightmap, row, column),
+        })
+    }).collect();
+
+    peaks.sort_by(|a, b| b.prominence.total_cmp(&a.prominence));
+    peaks
+}
+
+// Root of a sample in the union-find, halving the path on the way up.
+fn find(parent: &mut [usize], mut cell: usize) -> usize {
+    while parent[cell] != cell {
+        parent[cell] = parent[parent[cell]];
+        cell = parent[cell];
+    }
+    cell
+}
+
+// Distance from a sample to the nearest higher one, searching outwards one square ring at a time until
+// the rings are further away than the nearest higher sample found.
+fn isolation(heightmap: &[Vec<f32>], row: usize, column: usize) -> Option<f32> {
+    let height = heightmap.len() as isize;
+    let width = heightmap[0].len() as isize;
+    let (row, column) = (row as isize, column as isize);
+    let summit = heightmap[row as usize][column as usize];
+    let largest_ring = row.max(height - 1 - row).max(column).max(width - 1 - column);
+
+    let mut nearest_squared: Option<isize> = None;
+    for ring in 1..=largest_ring {
+        if nearest_squared.is_some_and(|nearest| ring * ring > nearest) {
+            break;
+        }
+        for di in -ring..=ring {
+            let y = row + di;
+            if y < 0 || y >= height {
+                continue;
+            }
+            // Whole rows at the top and bottom of the ring, only the two ends in between.
+            let step = if di.abs() == ring { 1 } else { 2 * ring };
+            let mut dj = -ring;
+            while dj <= ring {
+                let x = column + dj;
+                if x >= 0 && x < width && heightmap[y as usize][x as usize] > summit {
+                    let distance_squared = di * di + dj * dj;
+                    if nearest_squared.is_none_or(|nearest| distance_squared < nearest) {
+                        nearest_squared = Some(distance_squared);
+                    }
+                }
+                dj += step;
+            }
+        }
+    }
+
+    nearest_squared.map(|nearest| (nearest as f32).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hills 10 and 6 high on a 21 x 41 heightmap, centered on the middle row 20 columns apart.
+    fn two_hills() -> Vec<Vec<f32>> {
+        let hill = |i: usize, j: usize, column: f32, top: f32| {
+            top * (-((i as f32 - 10.0).powi(2) + (j as f32 - column).powi(2)) / 32.0).exp()
+        };
+        (0..21).map(|i| (0..41).map(|j| hill(i, j, 10.0, 10.0) + hill(i, j, 30.0, 6.0)).collect()).collect()
+    }
+
+    #[test]
+    fn the_lower_hill_rises_above_the_saddle_between_them() {
+        let heightmap = two_hills();
+        let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+        let peaks = find_peaks(&heightmap, 0.0);
+
+        assert_eq!(peaks.len(), 2);
+        let (higher, lower) = (peaks[0], peaks[1]);
+        assert_eq!((higher.row, higher.column, lower.row, lower.column), (10, 10, 10, 30));
+        assert_eq!((higher.key_col, higher.isolation), (None, None));
+        assert_eq!(higher.prominence, higher.height - lowest);
+
+        // The middle row is the ridge between the hills, and the saddle is its lowest point.
+        let saddle = (10..=30).min_by(|&a, &b| heightmap[10][a].total_cmp(&heightmap[10][b])).unwrap();
+        assert!((18..=22).contains(&saddle), "saddle at column {}", saddle);
+        assert_eq!(lower.key_col, Some((10, saddle)));
+        assert_eq!(lower.prominence, heightmap[10][30] - heightmap[10][saddle]);
+    }
+
+    #[test]
+    fn isolation_is_the_distance_to_the_nearest_higher_sample() {
+        let heightmap = two_hills();
+        let summit = heightmap[10][30];
+        let nearest = heightmap.iter().enumerate().flat_map(|(i, row)| row.iter().enumerate().map(move |(j, &value)| (i, j, value)))
+            .filter(|&(_, _, value)| value > summit)
+            .map(|(i, j, _)| ((i as f32 - 10.0).powi(2) + (j as f32 - 30.0).powi(2)).sqrt())
+            .fold(f32::INFINITY, f32::min);
+
+        let lower = find_peaks(&heightmap, 0.0)[1];
+        assert_eq!(lower.isolation, Some(nearest));
+        // On the flank of the higher hill, where it's 6 high about 4 columns from its summit.
+        assert!((15.0..17.0).contains(&nearest), "isolation {}", nearest);
+    }
+
+    #[test]
+    fn peaks_below_the_minimum_prominence_are_left_out() {
+        let heightmap = two_hills();
+        let peaks = find_peaks(&heightmap, 0.0);
+        let cutoff = (peaks[0].prominence + peaks[1].prominence) / 2.0;
+
+        let prominent = find_peaks(&heightmap, cutoff);
+        assert_eq!(prominent.len(), 1);
+        assert_eq!((prominent[0].row, prominent[0].column), (10, 10));
+        assert_eq!(find_peaks(&heightmap, peaks[1].prominence).len(), 2);
+    }
+
+    #[test]
+    fn a_plateau_is_a_single_peak() {
+        let mut heightmap = vec![vec![0.0; 8]; 8];
+        for row in &mut heightmap[2..5] {
+            row[3..6].fill(4.0);
+        }
+        // Parts of the flat ground around it flood as islands of their own, with no prominence.
+        let peaks = find_peaks(&heightmap, 1.0);
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(peaks[0].prominence, 4.0);
+    }
+}
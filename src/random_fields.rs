@@ -0,0 +1,221 @@
+use crate::fft_utils::apply_fft_to_rect_grid;
+use crate::terrain::gaussian;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rustfft::num_complex::Complex;
+
+// Stationary gaussian random fields with a chosen covariance, for rolling terrain or for fields like
+// moisture and temperature.
+
+#[derive(Clone, Copy)]
+pub enum CovarianceModel {
+    // sill * exp(-h / range). Rough, continuous but nowhere smooth.
+    Exponential,
+    // sill * exp(-(h / range)^2). Very smooth.
+    Gaussian,
+    // Reaches zero at h = range and stays there.
+    Spherical,
+    // Smoothness 0.5 is the exponential model, larger values get smoother and tend to the gaussian one.
+    Matern { smoothness: f32 },
+}
+
+#[derive(Clone, Copy)]
+pub struct CovarianceParams {
+    pub model: CovarianceModel,
+    // Distance in cells over which samples stay correlated.
+    pub range: f32,
+    // Variance of the correlated part of the field.
+    pub sill: f32,
+    // Variance of the uncorrelated noise added on top, which shows up as a jump of the variogram at 0.
+    pub nugget: f32,
+}
+
+impl CovarianceParams {
+    // Covariance of the correlated part of the field between two samples distance cells apart.
+    pub fn covariance(&self, distance: f32) -> f32 {
+        let h = (distance / self.range) as f64;
+
+        let correlation = match self.model {
+            CovarianceModel::Exponential => (-h).exp(),
+            CovarianceModel::Gaussian => (-h * h).exp(),
+            CovarianceModel::Spherical => if h < 1.0 { 1.0 - 1.5 * h + 0.5 * h.powi(3) } else { 0.0 },
+            CovarianceModel::Matern { smoothness } => matern_correlation(h, smoothness as f64),
+        };
+
+        self.sill * correlation as f32
+    }
+
+    // Semivariogram of the field, half the expected squared difference between two samples distance cells apart.
+    pub fn variogram(&self, distance: f32) -> f32 {
+        if distance == 0.0 {
+            0.0
+        } else {
+            self.nugget + self.sill - self.covariance(distance)
+        }
+    }
+}
+
+// Generates a width x height field with zero mean and the given covariance by circulant embedding: the
+// covariance is laid out on a torus at least twice the size of the field, whose FFT gives the spectrum
+// the white noise is shaped with. Models whose embedding isn't positive definite (the gaussian one with
+// a long range, for example) get a larger torus, and whatever negative part is left is clipped, which
+// slightly underestimates the variance at the longest lags. The same seed gives the same field.
+pub fn gaussian_random_field(width: usize, height: usize, params: &CovarianceParams, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut embedding_width = 2 * width;
+    let mut embedding_height = 2 * height;
+    let mut eigenvalues = circulant_eigenvalues(embedding_width, embedding_height, params);
+    for _ in 0..2 {
+        let largest = eigenvalues.iter().flatten().fold(0.0_f32, |largest, &value| largest.max(value));
+        let smallest = eigenvalues.iter().flatten().fold(0.0_f32, |smallest, &value| smallest.min(value));
+        if smallest >= -1e-4 * largest {
+            break;
+        }
+        embedding_width *= 2;
+        embedding_height *= 2;
+        eigenvalues = circulant_eigenvalues(embedding_width, embedding_height, params);
+    }
+
+    let scale = 1.0 / (embedding_width * embedding_height) as f32;
+    let mut field: Vec<Vec<Complex<f32>>> = eigenvalues.iter().map(|row|
+        row.iter().map(|&eigenvalue| {
+            let amplitude = (eigenvalue.max(0.0) * scale).sqrt();
+            Complex::new(gaussian(&mut rng), gaussian(&mut rng)) * amplitude
+        }).collect()
+    ).collect();
+
+    // The real and imaginary parts are two independent fields with the right covariance, only the real one is used.
+    apply_fft_to_rect_grid(&mut field);
+
+    let nugget_deviation = params.nugget.max(0.0).sqrt();
+    field[..height].iter().map(|row|
+        row[..width].iter().map(|value| value.re + gaussian(&mut rng) * nugget_deviation).collect()
+    ).collect()
+}
+
+// Eigenvalues of the circulant matrix of the covariance on a width x height torus, which are the FFT of
+// its first row.
+fn circulant_eigenvalues(width: usize, height: usize, params: &CovarianceParams) -> Vec<Vec<f32>> {
+    // The covariance is tabulated along the distance and interpolated, since the Matérn model is
+    // expensive to evaluate for every cell of the torus.
+    let largest_distance = ((width * width + height * height) as f32).sqrt() / 2.0;
+    let table: Vec<f32> = (0..=(largest_distance / TABLE_STEP) as usize + 1)
+        .map(|k| params.covariance(k as f32 * TABLE_STEP))
+        .collect();
+    let covariance = |distance: f32| -> f32 {
+        let position = distance / TABLE_STEP;
+        let k = position as usize;
+        let t = position - k as f32;
+        table[k] * (1.0 - t) + table[k + 1] * t
+    };
+
+    let mut covariances: Vec<Vec<Complex<f32>>> = (0..height).map(|i| {
+        let dy = i.min(height - i) as f32;
+        (0..width).map(|j| {
+            let dx = j.min(width - j) as f32;
+            Complex::new(covariance((dx * dx + dy * dy).sqrt()), 0.0)
+        }).collect()
+    }).collect();
+
+    apply_fft_to_rect_grid(&mut covariances);
+
+    covariances.into_iter().map(|row| row.into_iter().map(|value| value.re).collect()).collect()
+}
+
+// Distance in cells between two entries of the covariance table.
+const TABLE_STEP: f32 = 0.05;
+
+// Matérn correlation 2^(1 - v) / Γ(v) * h^v * K_v(h), which is 1 at h = 0.
+fn matern_correlation(h: f64, smoothness: f64) -> f64 {
+    if h < 1e-8 {
+        return 1.0;
+    }
+    2.0_f64.powf(1.0 - smoothness) / gamma(smoothness) * h.powf(smoothness) * bessel_k(smoothness, h)
+}
+
+// Modified Bessel function of the second kind, from its integral form
+// K_v(x) = ∫ exp(-x cosh t) cosh(v t) dt over t from 0 to infinity, integrated with the trapezoidal rule
+// until the integrand is negligible.
+fn bessel_k(order: f64, x: f64) -> f64 {
+    let step: f64 = 0.01;
+    let mut sum = 0.5 * (-x).exp();
+    let mut t = step;
+    loop {
+        let value = (-x * t.cosh() + order * t).exp() * 0.5 * (1.0 + (-2.0 * order * t).exp());
+        sum += value;
+        if x * t.cosh() > 700.0 || (value < 1e-16 * sum && x * t.cosh() > order * t + 40.0) {
+            break;
+        }
+        t += step;
+    }
+    sum * step
+}
+
+// Gamma function from the Lanczos approximation.
+fn gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 8] = [
+        676.5203681218851,
+        -1259.1392167224028,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507343278686905,
+        -0.13857109526572012,
+        9.984_369_578_019_572e-6,
+        1.5056327351493116e-7,
+    ];
+
+    if x < 0.5 {
+        // Reflection formula.
+        std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x))
+    } else {
+        let x = x - 1.0;
+        let t = x + 7.5;
+        let series = COEFFICIENTS.iter().enumerate().fold(0.999_999_999_999_809_9, |sum, (k, coefficient)| sum + coefficient / (x + k as f64 + 1.0));
+        (2.0 * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * series
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal_analysis::semivariogram;
+
+    #[test]
+    fn empirical_variogram_follows_the_model() {
+        let models = [
+            CovarianceModel::Exponential,
+            CovarianceModel::Gaussian,
+            CovarianceModel::Spherical,
+            CovarianceModel::Matern { smoothness: 1.5 },
+        ];
+        for model in models {
+            for nugget in [0.0, 0.5] {
+                let params = CovarianceParams { model, range: 6.0, sill: 2.0, nugget };
+                let field = gaussian_random_field(128, 128, &params, 9);
+                for lag in [1, 2, 4, 8] {
+                    let empirical = semivariogram(&field, lag).unwrap();
+                    let expected = params.variogram(lag as f32);
+                    assert!((empirical - expected).abs() < 0.15 * expected, "variogram {} instead of {} at lag {}", empirical, expected, lag);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn matern_with_smoothness_one_half_is_exponential() {
+        let matern = CovarianceParams { model: CovarianceModel::Matern { smoothness: 0.5 }, range: 5.0, sill: 3.0, nugget: 0.0 };
+        let exponential = CovarianceParams { model: CovarianceModel::Exponential, ..matern };
+        for distance in [0.0, 1.0, 2.5, 5.0, 12.0] {
+            assert!((matern.covariance(distance) - exponential.covariance(distance)).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn the_seed_decides_the_field() {
+        let params = CovarianceParams { model: CovarianceModel::Spherical, range: 4.0, sill: 1.0, nugget: 0.1 };
+        assert_eq!(gaussian_random_field(24, 16, &params, 1), gaussian_random_field(24, 16, &params, 1));
+        assert_ne!(gaussian_random_field(24, 16, &params, 1), gaussian_random_field(24, 16, &params, 2));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::mesh::MeshData;
+use bevy::math::{Mat4, Quat, Vec2, Vec3, Vec4};
+use std::f32::consts::PI;
+
+// Perspective previews of terrain meshes rendered on the CPU, for machines without a GPU. Triangles are
+// projected like Bevy's default camera does, filled with a depth buffer and lit by a directional light
+// per pixel, with the normals and colors interpolated perspective correctly.
+
+// Near and far planes of Bevy's default perspective projection.
+const NEAR: f32 = 0.1;
+const FAR: f32 = 1000.0;
+
+#[derive(Clone, Copy)]
+pub struct RasterParams {
+    pub width: u32,
+    pub height: u32,
+    // Position of the camera and the point it looks at, with y up.
+    pub eye: Vec3,
+    pub target: Vec3,
+    // Vertical field of view in radians.
+    pub fov: f32,
+    // Direction the light travels in.
+    pub light_direction: Vec3,
+    // Fraction of the light that reaches surfaces facing away from it.
+    pub ambient: f32,
+    pub background: [u8; 3],
+}
+
+impl RasterParams {
+    // The framing of the viewer for a terrain of size x size samples: its default window, camera and
+    // sun.
+    pub fn viewer(size: usize) -> Self {
+        RasterParams {
+            width: 1280,
+            height: 720,
+            eye: Vec3::new(size as f32 - 10.0, size as f32 - 10.0, 22.0),
+            target: Vec3::ZERO,
+            fov: PI / 4.0,
+            light_direction: Quat::from_rotation_x(-PI / 20.0) * Vec3::NEG_Z,
+            ambient: 0.3,
+            background: [0, 0, 0],
+        }
+    }
+}
+
+// Renders a mesh with a color per vertex, white without colors. Triangles facing away from the camera
+// are culled like Bevy does, so only the counterclockwise side of the mesh shows.
+pub fn render_mesh(mesh: &MeshData, colors: Option<&[[f32; 3]]>, params: &RasterParams) -> image::RgbImage {
+    let (width, height) = (params.width as usize, params.height as usize);
+    let view_projection = Mat4::perspective_rh(params.fov, width as f32 / height as f32, NEAR, FAR)
+        * Mat4::look_at_rh(params.eye, params.target, Vec3::Y);
+    let clip: Vec<Vec4> = mesh.positions.iter().map(|&[x, y, z]| view_projection * Vec4::new(x, y, z, 1.0)).collect();
+    let towards_light = -params.light_direction.normalize();
+
+    // Reciprocal of the depth of the closest surface so far at every pixel, 0.0 where there is none.
+    let mut depth = vec![0.0_f32; width * height];
+    let mut pixels = vec![params.background.map(|channel| channel as f32 / 255.0); width * height];
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let vertices = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        // Triangles reaching behind the near plane are left out rather than clipped.
+        if vertices.iter().any(|&vertex| clip[vertex].w < NEAR || clip[vertex].w > FAR) {
+            continue;
+        }
+
+        // Pixel coordinates, with y down.
+        let screen = vertices.map(|vertex| {
+            let ndc = clip[vertex] / clip[vertex].w;
+            Vec2::new((ndc.x + 1.0) * 0.5 * width as f32, (1.0 - ndc.y) * 0.5 * height as f32)
+        });
+        let area = edge(screen[0], screen[1], screen[2]);
+        // Counterclockwise triangles turn clockwise with y down, which makes their area negative.
+        if area >= 0.0 {
+            continue;
+        }
+
+        let left = screen.iter().fold(f32::INFINITY, |left, point| left.min(point.x)).floor().max(0.0) as usize;
+        let right = screen.iter().fold(f32::NEG_INFINITY, |right, point| right.max(point.x)).ceil().min(width as f32) as usize;
+        let top = screen.iter().fold(f32::INFINITY, |top, point| top.min(point.y)).floor().max(0.0) as usize;
+        let bottom = screen.iter().fold(f32::NEG_INFINITY, |bottom, point| bottom.max(point.y)).ceil().min(height as f32) as usize;
+        let reciprocal_w = vertices.map(|vertex| 1.0 / clip[vertex].w);
+
+        for y in top..bottom {
+            for x in left..right {
+                let point = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                // Barycentric coordinates, all positive inside the triangle.
+                let weights = [
+                    edge(screen[1], screen[2], point) / area,
+                    edge(screen[2], screen[0], point) / area,
+                    edge(screen[0], screen[1], point) / area,
+                ];
+                if weights.iter().any(|&weight| weight < 0.0) {
+                    continue;
+                }
+
+                let pixel = y * width + x;
+                let pixel_depth: f32 = (0..3).map(|k| weights[k] * reciprocal_w[k]).sum();
+                if pixel_depth <= depth[pixel] {
+                    continue;
+                }
+                depth[pixel] = pixel_depth;
+
+                // Attributes are interpolated linearly in 1 / w, then divided back.
+                let interpolate = |attribute: &dyn Fn(usize) -> Vec3| -> Vec3 {
+                    (0..3).map(|k| attribute(vertices[k]) * weights[k] * reciprocal_w[k]).sum::<Vec3>() / pixel_depth
+                };
+                let normal = interpolate(&|vertex| Vec3::from(mesh.normals[vertex])).normalize_or_zero();
+                let color = match colors {
+                    Some(colors) => interpolate(&|vertex| Vec3::from(colors[vertex])),
+                    None => Vec3::ONE,
+                };
+
+                let light = params.ambient + (1.0 - params.ambient) * normal.dot(towards_light).max(0.0);
+                pixels[pixel] = (color * light).into();
+            }
+        }
+    }
+
+    image::RgbImage::from_fn(params.width, params.height, |x, y| {
+        let color = pixels[y as usize * width + x as usize];
+        image::Rgb(color.map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8))
+    })
+}
+
+// Twice the signed area of the triangle a, b, c, positive when it turns counterclockwise with y up.
+fn edge(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::{PerspectiveProjection, Transform};
+
+    // Camera on the z axis looking down it at a small image, lit head on so the colors come out unshaded.
+    fn params() -> RasterParams {
+        RasterParams {
+            width: 64,
+            height: 64,
+            eye: Vec3::new(0.0, 0.0, 5.0),
+            target: Vec3::ZERO,
+            fov: PI / 4.0,
+            light_direction: Vec3::NEG_Z,
+            ambient: 0.3,
+            background: [0, 0, 0],
+        }
+    }
+
+    // A triangle around the z axis at depth z, counterclockwise seen from the camera unless flipped.
+    fn triangle(mesh: &mut MeshData, z: f32, flipped: bool) {
+        let first = mesh.positions.len() as u32;
+        mesh.positions.extend_from_slice(&[[-1.0, -1.0, z], [1.0, -1.0, z], [0.0, 1.0, z]]);
+        mesh.normals.extend_from_slice(&[[0.0, 0.0, 1.0]; 3]);
+        if flipped {
+            mesh.indices.extend_from_slice(&[first, first + 2, first + 1]);
+        } else {
+            mesh.indices.extend_from_slice(&[first, first + 1, first + 2]);
+        }
+    }
+
+    fn center(image: &image::RgbImage) -> [u8; 3] {
+        image.get_pixel(32, 32).0
+    }
+
+    #[test]
+    fn the_nearest_triangle_wins_whatever_the_order() {
+        let red = [1.0, 0.0, 0.0];
+        let green = [0.0, 1.0, 0.0];
+
+        for near_first in [false, true] {
+            let mut mesh = MeshData { positions: Vec::new(), normals: Vec::new(), indices: Vec::new() };
+            let depths = if near_first { [1.0, 0.0] } else { [0.0, 1.0] };
+            triangle(&mut mesh, depths[0], false);
+            triangle(&mut mesh, depths[1], false);
+            let colors: Vec<[f32; 3]> =
+                depths.iter().flat_map(|&z| [if z > 0.5 { green } else { red }; 3]).collect();
+
+            let image = render_mesh(&mesh, Some(&colors), &params());
+            assert_eq!(center(&image), [0, 255, 0], "near triangle drawn first: {}", near_first);
+        }
+    }
+
+    #[test]
+    fn triangles_facing_away_are_culled() {
+        let mut mesh = MeshData { positions: Vec::new(), normals: Vec::new(), indices: Vec::new() };
+        triangle(&mut mesh, 0.0, true);
+        assert_eq!(center(&render_mesh(&mesh, None, &params())), [0, 0, 0]);
+
+        let mut mesh = MeshData { positions: Vec::new(), normals: Vec::new(), indices: Vec::new() };
+        triangle(&mut mesh, 0.0, false);
+        assert_eq!(center(&render_mesh(&mesh, None, &params())), [255, 255, 255]);
+    }
+
+    #[test]
+    fn perspective_makes_far_triangles_smaller() {
+        let coverage = |z: f32| {
+            let mut mesh = MeshData { positions: Vec::new(), normals: Vec::new(), indices: Vec::new() };
+            triangle(&mut mesh, z, false);
+            render_mesh(&mesh, None, &params()).pixels().filter(|pixel| pixel.0 != [0, 0, 0]).count() as f32
+        };
+
+        // At distances 4 and 6 from the eye, the projected area goes with the inverse square distance.
+        let ratio = coverage(1.0) / coverage(-1.0);
+        assert!((ratio - (6.0_f32 / 4.0).powi(2)).abs() < 0.15, "coverage ratio {}", ratio);
+    }
+
+    #[test]
+    fn viewer_framing_matches_the_viewer_camera() {
+        let size = 128;
+        let params = RasterParams::viewer(size);
+
+        let camera = Transform::from_xyz(size as f32 - 10.0, size as f32 - 10.0, 22.0).looking_at(Vec3::ZERO, Vec3::Y);
+        let view = Mat4::look_at_rh(params.eye, params.target, Vec3::Y);
+        assert!(view.abs_diff_eq(camera.compute_matrix().inverse(), 1e-4));
+
+        let projection = PerspectiveProjection::default();
+        assert_eq!(params.fov, projection.fov);
+        assert_eq!(NEAR, projection.near);
+        assert_eq!(FAR, projection.far);
+    }
+}
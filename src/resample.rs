@@ -0,0 +1,172 @@
+use crate::fft_utils::{apply_fft_to_rect_grid, apply_ifft_to_rect_grid, frequency_bin};
+use rustfft::num_complex::Complex;
+use std::f32::consts::PI;
+
+// How the heightmap is prepared for the transform. The FFT treats the heightmap as periodic, so on a
+// heightmap whose opposite edges don't match the jump between them rings through the whole result.
+#[derive(Clone, Copy)]
+pub enum ResampleWindow {
+    // Use the heightmap as is, for heightmaps that already tile.
+    None,
+    // Taper the whole heightmap towards its mean with a Hann window.
+    Hann,
+    // Taper only the outer alpha / 2 of each side with a Tukey window, alpha in (0, 1].
+    Tukey(f32),
+    // Mirror the heightmap into a twice as large one that tiles without any jump.
+    Mirror,
+}
+
+// Resamples a heightmap to new_width x new_height by zero-padding (upsampling) or truncating
+// (downsampling) its spectrum. This is band limited interpolation when upsampling and anti-aliased
+// decimation when downsampling. Sample (i, j) of the result sits at (i * height / new_height,
+// j * width / new_width) of the input.
+//
+// Hann and Tukey windows taper the edges before the transform, which removes the ringing from the
+// interior. Dividing the taper back out afterwards would blow up the error where the taper is close to
+// zero, so instead the result is blended by the taper into the Mirror resampling, which takes over
+// towards the edges. Mirror alone transforms a grid four times as large.
+pub fn fft_resample(heightmap: &[Vec<f32>], new_width: usize, new_height: usize, window: ResampleWindow) -> Vec<Vec<f32>> {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+
+    let mean = heightmap.iter().flatten().map(|&value| value as f64).sum::<f64>() / (width * height) as f64;
+    let mean = mean as f32;
+
+    if let ResampleWindow::Mirror = window {
+        let mirrored: Vec<Vec<f32>> = (0..2 * height).map(|i| {
+            let row = &heightmap[if i < height { i } else { 2 * height - 1 - i }];
+            (0..2 * width).map(|j| row[if j < width { j } else { 2 * width - 1 - j }]).collect()
+        }).collect();
+
+        let resampled = fft_resample(&mirrored, 2 * new_width, 2 * new_height, ResampleWindow::None);
+        return resampled.into_iter().take(new_height).map(|row| row.into_iter().take(new_width).collect()).collect();
+    }
+
+    let alpha = match window {
+        ResampleWindow::Hann => Some(1.0),
+        ResampleWindow::Tukey(alpha) => Some(alpha),
+        _ => None,
+    };
+
+    let mut spectrum: Vec<Vec<Complex<f32>>> = heightmap.iter().enumerate().map(|(i, row)|
+        row.iter().enumerate().map(|(j, &value)| {
+            let taper = match alpha {
+                Some(alpha) => tukey(i as f32 / (height - 1).max(1) as f32, alpha) * tukey(j as f32 / (width - 1).max(1) as f32, alpha),
+                None => 1.0,
+            };
+            Complex::new((value - mean) * taper, 0.0)
+        }).collect()
+    ).collect();
+
+    apply_fft_to_rect_grid(&mut spectrum);
+
+    // Move every frequency that exists in both sizes to its bin in the new spectrum.
+    let mut new_spectrum = vec![vec![Complex::new(0.0, 0.0); new_width]; new_height];
+    let scale = (new_width * new_height) as f32 / (width * height) as f32;
+    let column_targets: Vec<Vec<(usize, f32)>> = (0..width).map(|j| resampled_bins(j, width, new_width)).collect();
+    for (i, row) in spectrum.iter().enumerate() {
+        let targets_i = resampled_bins(i, height, new_height);
+        for (value, targets_j) in row.iter().zip(column_targets.iter()) {
+            for &(new_i, weight_i) in &targets_i {
+                for &(new_j, weight_j) in targets_j {
+                    new_spectrum[new_i][new_j] += value * (weight_i * weight_j * scale);
+                }
+            }
+        }
+    }
+
+    apply_ifft_to_rect_grid(&mut new_spectrum);
+
+    let alpha = match alpha {
+        Some(alpha) => alpha,
+        None => return new_spectrum.iter().map(|row| row.iter().map(|value| value.re + mean).collect()).collect(),
+    };
+
+    // The windowed result is value / taper + mean, and taper times that plus (1 - taper) times the
+    // mirrored result needs no division.
+    let mirrored = fft_resample(heightmap, new_width, new_height, ResampleWindow::Mirror);
+    new_spectrum.iter().zip(mirrored.iter()).enumerate().map(|(i, (row, mirrored_row))| {
+        // Position of the new sample in the input, as a fraction of the window.
+        let y = (i as f32 * height as f32 / new_height as f32 / (height - 1).max(1) as f32).min(1.0);
+        row.iter().zip(mirrored_row.iter()).enumerate().map(|(j, (value, mirrored))| {
+            let x = (j as f32 * width as f32 / new_width as f32 / (width - 1).max(1) as f32).min(1.0);
+            let taper = tukey(y, alpha) * tukey(x, alpha);
+            value.re + taper * mean + (1.0 - taper) * mirrored
+        }).collect()
+    }).collect()
+}
+
+// Bins of the new spectrum that bin k of the old one goes to, with the share of the value each gets.
+// A Nyquist bin (only in even sizes) holds both the positive and the negative frequency, so it is split
+// in two when upsampling and the two sides are merged into it when downsampling.
+fn resampled_bins(k: usize, size: usize, new_size: usize) -> Vec<(usize, f32)> {
+    let frequency = frequency_bin(k, size);
+
+    if size.is_multiple_of(2) && k == size / 2 && new_size > size {
+        return vec![(k, 0.5), (new_size - k, 0.5)];
+    }
+
+    let limit = new_size as f32 / 2.0;
+    if frequency.abs() < limit || (new_size.is_multiple_of(2) && frequency.abs() == limit) {
+        let index = if frequency >= 0.0 { frequency as usize } else { new_size - (-frequency) as usize };
+        vec![(index % new_size, 1.0)]
+    } else {
+        Vec::new()
+    }
+}
+
+// Tukey window at x in [0, 1], alpha = 1 is a Hann window.
+fn tukey(x: f32, alpha: f32) -> f32 {
+    if x < alpha / 2.0 {
+        0.5 * (1.0 - (2.0 * PI * x / alpha).cos())
+    } else if x > 1.0 - alpha / 2.0 {
+        0.5 * (1.0 - (2.0 * PI * (1.0 - x) / alpha).cos())
+    } else {
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_resample_heightmaps_that_dont_tile() {
+        let wave = |y: f32, x: f32| 2.0 * (0.37 * x + 0.2).sin() * (0.23 * y).cos();
+        let heightmap: Vec<Vec<f32>> = (0..32).map(|i| (0..32).map(|j| wave(i as f32, j as f32)).collect()).collect();
+
+        for window in [ResampleWindow::Hann, ResampleWindow::Tukey(0.5), ResampleWindow::Mirror] {
+            let resampled = fft_resample(&heightmap, 64, 64, window);
+            for (i, row) in resampled.iter().enumerate() {
+                for (j, &value) in row.iter().enumerate() {
+                    let error = (value - wave(i as f32 / 2.0, j as f32 / 2.0)).abs();
+                    // The last row and column lie past the input and are extrapolated.
+                    let tolerance = if i == 63 || j == 63 { 0.25 } else { 0.05 };
+                    assert!(error < tolerance, "error {} at ({}, {})", error, i, j);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_single_sample_stays_put() {
+        for window in [ResampleWindow::None, ResampleWindow::Hann, ResampleWindow::Tukey(0.5), ResampleWindow::Mirror] {
+            assert_eq!(fft_resample(&[vec![3.5]], 1, 1, window), vec![vec![3.5]]);
+        }
+    }
+
+    #[test]
+    fn upsampling_and_downsampling_a_tiling_heightmap_gives_it_back() {
+        let heightmap: Vec<Vec<f32>> = (0..16).map(|i| (0..16).map(|j| {
+            (2.0 * PI * i as f32 / 16.0).sin() + (2.0 * PI * 3.0 * j as f32 / 16.0).cos()
+        }).collect()).collect();
+
+        let upsampled = fft_resample(&heightmap, 48, 32, ResampleWindow::None);
+        let downsampled = fft_resample(&upsampled, 16, 16, ResampleWindow::None);
+        for (row, original_row) in downsampled.iter().zip(heightmap.iter()) {
+            for (value, original) in row.iter().zip(original_row.iter()) {
+                assert!((value - original).abs() < 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::derivatives::{surface_derivatives, DerivativeParams, SlopeUnit};
+
+// Shaded relief images of heightmaps on the CPU, to preview terrain without the viewer: hillshade, a
+// hypsometric color ramp and slope shading, multiplied together into an RGB image. Rendering is
+// deterministic, so images of the same heightmap can be compared pixel for pixel.
+
+#[derive(Clone, Copy)]
+pub struct HillshadeParams {
+    // Direction the sun shines from, in degrees clockwise from north, the first row of the heightmap.
+    pub azimuth: f32,
+    // Angle of the sun above the horizon in degrees.
+    pub altitude: f32,
+    // Vertical exaggeration, heights are multiplied by it.
+    pub z_factor: f32,
+    // Light from the northwest quadrant, 225, 270, 315 and 360 degrees, each weighted by how well it
+    // brings out the slope at each sample, instead of the azimuth alone. Shows features of any
+    // orientation, which a single sun leaves flat when it shines along them.
+    pub multidirectional: bool,
+}
+
+impl Default for HillshadeParams {
+    fn default() -> Self {
+        HillshadeParams {
+            azimuth: 315.0,
+            altitude: 45.0,
+            z_factor: 1.0,
+            multidirectional: false,
+        }
+    }
+}
+
+// Colors by relative height from the lowest (0.0) to the highest (1.0) sample: lowland greens through
+// yellows and browns to snow.
+pub const HYPSOMETRIC_RAMP: &[(f32, [u8; 3])] = &[
+    (0.0, [46, 110, 70]),
+    (0.2, [110, 160, 90]),
+    (0.4, [215, 205, 130]),
+    (0.6, [190, 140, 80]),
+    (0.8, [140, 100, 80]),
+    (0.95, [235, 235, 235]),
+    (1.0, [255, 255, 255]),
+];
+
+#[derive(Clone, Copy)]
+pub struct ReliefParams {
+    pub hillshade: HillshadeParams,
+    // How much the hillshade darkens the colors, from 0.0 for not at all to 1.0 for black shadows.
+    pub hillshade_strength: f32,
+    // How much steep slopes are darkened, from 0.0 for not at all to 1.0 for black vertical slopes.
+    pub slope_strength: f32,
+    // Stops of relative height and color, in increasing order of height.
+    pub ramp: &'static [(f32, [u8; 3])],
+}
+
+impl Default for ReliefParams {
+    fn default() -> Self {
+        ReliefParams {
+            hillshade: HillshadeParams::default(),
+            hillshade_strength: 0.7,
+            slope_strength: 0.3,
+            ramp: HYPSOMETRIC_RAMP,
+        }
+    }
+}
+
+// Brightness of every sample lit by the sun, from 0.0 in shadow to 1.0 facing it, the cosine of the
+// angle between the sun and the normal of the surface. Heights are in the units of one sample.
+pub fn hillshade(heightmap: &[Vec<f32>], params: &HillshadeParams) -> Vec<Vec<f32>> {
+    let derivatives = surface_derivatives(heightmap, &DerivativeParams::default());
+    let (sin_altitude, cos_altitude) = params.altitude.to_radians().sin_cos();
+    let azimuths: &[f32] = if params.multidirectional { &[225.0, 270.0, 315.0, 360.0] } else { &[params.azimuth] };
+    // Horizontal directions towards the sun, east and south, since x runs east and y south.
+    let suns: Vec<(f32, f32)> = azimuths.iter().map(|azimuth| {
+        let (sin, cos) = azimuth.to_radians().sin_cos();
+        (sin, -cos)
+    }).collect();
+
+    derivatives.dx.iter().zip(derivatives.dy.iter()).map(|(row_dx, row_dy)|
+        row_dx.iter().zip(row_dy.iter()).map(|(&dx, &dy)| {
+            let (p, q) = (dx * params.z_factor, dy * params.z_factor);
+            let norm = (1.0 + p * p + q * q).sqrt();
+            let (mut shade, mut total_weight) = (0.0, 0.0);
+            for &(east, south) in &suns {
+                // The normal is (-p, -q, 1) / norm.
+                let lit = ((sin_altitude - cos_altitude * (p * east + q * south)) / norm).max(0.0);
+                // Each sun counts by the squared cosine of its angle to the gradient, a single sun fully.
+                let weight = if suns.len() == 1 { 1.0 } else { (p * east + q * south).powi(2) };
+                shade += weight * lit;
+                total_weight += weight;
+            }
+            if total_weight > 0.0 {
+                shade / total_weight
+            } else {
+                // Flat, every sun lights it the same.
+                sin_altitude
+            }
+        }).collect()
+    ).collect()
+}
+
+// Brightness of every sample by its slope, 1.0 on flats down to 0.0 on vertical slopes.
+pub fn slope_shade(heightmap: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    surface_derivatives(heightmap, &DerivativeParams::default()).slope(SlopeUnit::Degrees).iter().map(|row|
+        row.iter().map(|slope| 1.0 - slope / 90.0).collect()
+    ).collect()
+}
+
+// Color of every sample from the ramp by its height relative to the height range, linearly
+// interpolated between the stops, with channels from 0.0 to 1.0.
+pub fn hypsometric_tint(heightmap: &[Vec<f32>], ramp: &[(f32, [u8; 3])]) -> Vec<Vec<[f32; 3]>> {
+    let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+    let highest = heightmap.iter().flatten().fold(f32::NEG_INFINITY, |highest, &value| highest.max(value));
+    let range = highest - lowest;
+
+    let color = |relative: f32| -> [f32; 3] {
+        let k = ramp.partition_point(|&(stop, _)| stop < relative);
+        let channels = |color: [u8; 3]| color.map(|channel| channel as f32 / 255.0);
+        if k == 0 {
+            return channels(ramp[0].1);
+        }
+        if k == ramp.len() {
+            return channels(ramp[ramp.len() - 1].1);
+        }
+        let ((low, low_color), (high, high_color)) = (ramp[k - 1], ramp[k]);
+        let t = (relative - low) / (high - low);
+        let (low_color, high_color) = (channels(low_color), channels(high_color));
+        [0, 1, 2].map(|c| low_color[c] * (1.0 - t) + high_color[c] * t)
+    };
+
+    heightmap.iter().map(|row|
+        row.iter().map(|&value| color(if range > 0.0 { (value - lowest) / range } else { 0.0 })).collect()
+    ).collect()
+}
+
+// Hypsometric colors darkened by the hillshade and the slope shading.
+pub fn render_relief(heightmap: &[Vec<f32>], params: &ReliefParams) -> image::RgbImage {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let colors = hypsometric_tint(heightmap, params.ramp);
+    let hillshade = hillshade(heightmap, &params.hillshade);
+    let slope_shade = slope_shade(heightmap);
+
+    image::RgbImage::from_fn(width as u32, height as u32, |x, y| {
+        let (i, j) = (y as usize, x as usize);
+        let brightness = (1.0 - params.hillshade_strength + params.hillshade_strength * hillshade[i][j])
+            * (1.0 - params.slope_strength + params.slope_strength * slope_shade[i][j]);
+        image::Rgb(colors[i][j].map(|channel| (channel * brightness * 255.0).round().clamp(0.0, 255.0) as u8))
+    })
+}
+
+// Saves the shaded relief of a heightmap as a PNG.
+pub fn save_relief_image(heightmap: &[Vec<f32>], params: &ReliefParams, path: &str) -> image::ImageResult<()> {
+    render_relief(heightmap, params).save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::plane;
+
+    fn all_close(map: &[Vec<f32>], expected: f32) -> bool {
+        map.iter().flatten().all(|value| (value - expected).abs() < 1e-5)
+    }
+
+    #[test]
+    fn planes_get_a_constant_shade() {
+        // The sun is 45 degrees above the western horizon. A 45 degree slope facing west faces it, the
+        // same slope facing east is parallel to its rays, and flat ground gets the sine of its altitude.
+        let sun = HillshadeParams { azimuth: 270.0, ..HillshadeParams::default() };
+        assert!(all_close(&hillshade(&plane(8, 1.0, 0.0), &sun), 1.0));
+        assert!(all_close(&hillshade(&plane(8, -1.0, 0.0), &sun), 0.0));
+        assert!(all_close(&hillshade(&plane(8, 0.0, 0.0), &sun), 45.0_f32.to_radians().sin()));
+
+        // A slope facing north is lit from the side.
+        let expected = 45.0_f32.to_radians().sin() / 2.0_f32.sqrt();
+        assert!(all_close(&hillshade(&plane(8, 0.0, 1.0), &sun), expected));
+    }
+
+    #[test]
+    fn ramp_ends_color_the_lowest_and_highest_samples() {
+        let colors = hypsometric_tint(&plane(8, 1.0, 1.0), HYPSOMETRIC_RAMP);
+        let channels = |color: [u8; 3]| color.map(|channel| channel as f32 / 255.0);
+        assert_eq!(colors[0][0], channels(HYPSOMETRIC_RAMP[0].1));
+        assert_eq!(colors[7][7], channels(HYPSOMETRIC_RAMP[HYPSOMETRIC_RAMP.len() - 1].1));
+    }
+
+    #[test]
+    fn relief_images_are_reproducible() {
+        // Flat ground is the lowest color of the ramp, darkened by the hillshade of the sun at 45 degrees.
+        let flat = render_relief(&plane(8, 0.0, 0.0), &ReliefParams::default());
+        assert!(flat.pixels().all(|pixel| pixel.0 == [37, 87, 56]));
+
+        let heightmap: Vec<Vec<f32>> = (0..16).map(|i| (0..16).map(|j| ((i * 7 + j * 3) % 11) as f32).collect()).collect();
+        let params = ReliefParams {
+            hillshade: HillshadeParams { multidirectional: true, ..HillshadeParams::default() },
+            ..ReliefParams::default()
+        };
+        assert_eq!(render_relief(&heightmap, &params), render_relief(&heightmap, &params));
+        assert!(all_close(&hillshade(&plane(8, 0.0, 0.0), &params.hillshade), 45.0_f32.to_radians().sin()));
+    }
+}
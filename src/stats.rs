@@ -0,0 +1,245 @@
+use crate::correlation::{autocorrelation, correlation_lengths};
+use crate::derivatives::{surface_derivatives, DerivativeParams, SlopeUnit, SurfaceDerivatives};
+use serde::Serialize;
+
+// Surface texture parameters in the style of ISO 25178, so the output of different generators can be
+// compared by the numbers. Heights are measured from the mean height, distances in samples, and areas
+// and slopes assume one unit between neighbouring samples, the same as the viewer mesh.
+
+#[derive(Serialize)]
+pub struct TerrainStats {
+    // Arithmetic mean height, the mean absolute distance from the mean height.
+    pub sa: f32,
+    // Root mean square height.
+    pub sq: f32,
+    // Skewness of the heights. Positive when a few peaks stick out of a flat plain, negative when a few
+    // valleys cut into a plateau.
+    pub ssk: f32,
+    // Kurtosis of the heights, 3.0 for gaussian heights, higher when there are extreme peaks or valleys.
+    pub sku: f32,
+    // Peak to valley height, the highest minus the lowest sample.
+    pub sz: f32,
+    // Developed interfacial area ratio, how much larger the surface is than the ground it covers, 0.0 for
+    // a flat heightmap.
+    pub sdr: f32,
+    // Autocorrelation length, the shortest distance at which the autocorrelation decays to 0.2.
+    pub sal: f32,
+    // Texture aspect ratio, the shortest over the longest distance at which the autocorrelation decays
+    // to 0.2. Close to 1.0 for isotropic terrain, close to 0.0 for terrain with a strong direction.
+    pub str: f32,
+    pub hypsometric_curve: HypsometricCurve,
+    // Mean height relative to the height range, the area under the hypsometric curve. Young, barely
+    // eroded terrain has values above 0.6, old worn down terrain below 0.35.
+    pub hypsometric_integral: f32,
+    // Slope in degrees from Horn's stencil, in 5 degree bins from 0 to 90.
+    pub slope_histogram: Histogram,
+    // Direction the slope faces in degrees clockwise from north, the first row of the heightmap, in 8
+    // bins centered on the compass directions. Flat samples have no aspect and aren't counted.
+    pub aspect_histogram: Histogram,
+}
+
+#[derive(Serialize)]
+pub struct HypsometricCurve {
+    // Height relative to the height range, from 0.0 at the lowest sample to 1.0 at the highest.
+    pub relative_height: Vec<f32>,
+    // Fraction of the heightmap at or above each relative height.
+    pub area_fraction: Vec<f32>,
+}
+
+#[derive(Serialize)]
+pub struct Histogram {
+    // Edges of the bins, one more than there are bins.
+    pub bin_edges: Vec<f32>,
+    // Fraction of the samples in each bin.
+    pub fractions: Vec<f32>,
+}
+
+// Threshold the autocorrelation decays to for the autocorrelation length and texture aspect ratio.
+const CORRELATION_THRESHOLD: f32 = 0.2;
+// Number of levels of the hypsometric curve.
+const HYPSOMETRIC_LEVELS: usize = 21;
+
+pub fn terrain_stats(heightmap: &[Vec<f32>]) -> TerrainStats {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    let samples = (width * height) as f64;
+
+    let mean = heightmap.iter().flatten().map(|&value| value as f64).sum::<f64>() / samples;
+    let (mut sum_abs, mut sum2, mut sum3, mut sum4) = (0.0, 0.0, 0.0, 0.0);
+    for &value in heightmap.iter().flatten() {
+        let z = value as f64 - mean;
+        sum_abs += z.abs();
+        sum2 += z * z;
+        sum3 += z * z * z;
+        sum4 += z * z * z * z;
+    }
+    let sq = (sum2 / samples).sqrt();
+    let (ssk, sku) = if sq > 0.0 {
+        (sum3 / samples / sq.powi(3), sum4 / samples / sq.powi(4))
+    } else {
+        (0.0, 0.0)
+    };
+
+    let lowest = heightmap.iter().flatten().fold(f32::INFINITY, |lowest, &value| lowest.min(value));
+    let highest = heightmap.iter().flatten().fold(f32::NEG_INFINITY, |highest, &value| highest.max(value));
+    let range = highest - lowest;
+
+    let lengths = correlation_lengths(&autocorrelation(heightmap), CORRELATION_THRESHOLD);
+    let derivatives = surface_derivatives(heightmap, &DerivativeParams::default());
+
+    TerrainStats {
+        sa: (sum_abs / samples) as f32,
+        sq: sq as f32,
+        ssk: ssk as f32,
+        sku: sku as f32,
+        sz: range,
+        sdr: developed_area_ratio(heightmap),
+        sal: lengths.shortest,
+        str: if lengths.major > 0.0 { lengths.shortest / lengths.major } else { 1.0 },
+        hypsometric_curve: hypsometric_curve(heightmap, lowest, range),
+        hypsometric_integral: if range > 0.0 { (mean as f32 - lowest) / range } else { 0.0 },
+        slope_histogram: slope_histogram(&derivatives),
+        aspect_histogram: aspect_histogram(&derivatives),
+    }
+}
+
+// Area of the surface triangulated the same as the mesh, two triangles per cell, over the area of the cells.
+fn developed_area_ratio(heightmap: &[Vec<f32>]) -> f32 {
+    let height = heightmap.len();
+    let width = heightmap[0].len();
+    if width < 2 || height < 2 {
+        return 0.0;
+    }
+
+    let triangle_area = |a: f32, b: f32, c: f32| -> f64 {
+        // Right triangle with unit legs along x and y from the corner at height a, to b along x and c along y.
+        let (dx, dy) = ((b - a) as f64, (c - a) as f64);
+        0.5 * (1.0 + dx * dx + dy * dy).sqrt()
+    };
+
+    let mut area = 0.0;
+    for rows in heightmap.windows(2) {
+        for j in 0..width - 1 {
+            area += triangle_area(rows[0][j], rows[0][j + 1], rows[1][j]);
+            area += triangle_area(rows[1][j + 1], rows[1][j], rows[0][j + 1]);
+        }
+    }
+
+    let cells = ((width - 1) * (height - 1)) as f64;
+    (area / cells - 1.0) as f32
+}
+
+fn hypsometric_curve(heightmap: &[Vec<f32>], lowest: f32, range: f32) -> HypsometricCurve {
+    let samples = heightmap.iter().map(|row| row.len()).sum::<usize>() as f32;
+    let relative_height: Vec<f32> = (0..HYPSOMETRIC_LEVELS).map(|k| k as f32 / (HYPSOMETRIC_LEVELS - 1) as f32).collect();
+
+    let area_fraction = relative_height.iter().map(|&level| {
+        let threshold = lowest + level * range;
+        heightmap.iter().flatten().filter(|&&value| value >= threshold).count() as f32 / samples
+    }).collect();
+
+    HypsometricCurve {
+        relative_height,
+        area_fraction,
+    }
+}
+
+fn slope_histogram(derivatives: &SurfaceDerivatives) -> Histogram {
+    let bins = 18;
+    let bin_width = 90.0 / bins as f32;
+    let mut counts = vec![0_usize; bins];
+
+    let slopes = derivatives.slope(SlopeUnit::Degrees);
+    for &slope in slopes.iter().flatten() {
+        counts[((slope / bin_width) as usize).min(bins - 1)] += 1;
+    }
+
+    histogram(&counts, 0.0, bin_width, slopes.len() * slopes[0].len())
+}
+
+fn aspect_histogram(derivatives: &SurfaceDerivatives) -> Histogram {
+    let bins = 8;
+    let bin_width = 360.0 / bins as f32;
+    let mut counts = vec![0_usize; bins];
+    let mut sloped = 0;
+
+    // Flat samples have a negative aspect.
+    for &aspect in derivatives.aspect().iter().flatten().filter(|&&aspect| aspect >= 0.0) {
+        // Bins are centered on the compass directions, so north covers -22.5 to 22.5 degrees.
+        counts[((aspect + bin_width / 2.0) / bin_width) as usize % bins] += 1;
+        sloped += 1;
+    }
+
+    histogram(&counts, -bin_width / 2.0, bin_width, sloped)
+}
+
+fn histogram(counts: &[usize], first_edge: f32, bin_width: f32, total: usize) -> Histogram {
+    Histogram {
+        bin_edges: (0..=counts.len()).map(|k| first_edge + k as f32 * bin_width).collect(),
+        fractions: counts.iter().map(|&count| if total > 0 { count as f32 / total as f32 } else { 0.0 }).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{hills, plane};
+
+    fn close(value: f32, expected: f32) -> bool {
+        (value - expected).abs() < 1e-4 * expected.abs().max(1.0)
+    }
+
+    #[test]
+    fn height_parameters_match_hand_computed_values() {
+        // Mean 1.0, eight samples 1.0 below it and one 8.0 above it.
+        let mut heightmap = vec![vec![0.0; 3]; 3];
+        heightmap[2][2] = 9.0;
+        let stats = terrain_stats(&heightmap);
+
+        assert!(close(stats.sa, 16.0 / 9.0), "sa {}", stats.sa);
+        assert!(close(stats.sq, 8.0_f32.sqrt()), "sq {}", stats.sq);
+        assert!(close(stats.ssk, 56.0 / 8.0_f32.powf(1.5)), "ssk {}", stats.ssk);
+        assert!(close(stats.sku, 456.0 / 64.0), "sku {}", stats.sku);
+        assert!(close(stats.sz, 9.0), "sz {}", stats.sz);
+        assert!(close(stats.hypsometric_integral, 1.0 / 9.0), "hypsometric integral {}", stats.hypsometric_integral);
+    }
+
+    #[test]
+    fn developed_area_grows_with_the_gradient() {
+        let flat = terrain_stats(&plane(16, 0.0, 0.0));
+        assert_eq!(flat.sdr, 0.0);
+        assert_eq!((flat.sa, flat.sq, flat.ssk, flat.sku, flat.sz), (0.0, 0.0, 0.0, 0.0, 0.0));
+
+        let inclined = terrain_stats(&plane(16, 0.5, 0.25));
+        assert!(close(inclined.sdr, (1.0_f32 + 0.5 * 0.5 + 0.25 * 0.25).sqrt() - 1.0), "sdr {}", inclined.sdr);
+    }
+
+    #[test]
+    fn a_linear_ramp_has_a_hypsometric_integral_of_one_half() {
+        let stats = terrain_stats(&plane(17, 1.0, 0.0));
+        assert!(close(stats.hypsometric_integral, 0.5), "hypsometric integral {}", stats.hypsometric_integral);
+
+        // Every column holds a seventeenth of the samples, so the area at or above each level falls in
+        // steps of 1 / 17.
+        let curve = &stats.hypsometric_curve;
+        assert_eq!(curve.relative_height.len(), HYPSOMETRIC_LEVELS);
+        assert_eq!((curve.area_fraction[0], curve.area_fraction[HYPSOMETRIC_LEVELS - 1]), (1.0, 1.0 / 17.0));
+        assert!(curve.area_fraction.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[test]
+    fn histograms_count_every_sample_once() {
+        let heightmap = hills(32);
+        let stats = terrain_stats(&heightmap);
+        let samples = (32 * 32) as f32;
+
+        for histogram in [&stats.slope_histogram, &stats.aspect_histogram] {
+            assert_eq!(histogram.bin_edges.len(), histogram.fractions.len() + 1);
+            let counts: Vec<f32> = histogram.fractions.iter().map(|fraction| fraction * samples).collect();
+            assert!(counts.iter().all(|count| (count - count.round()).abs() < 1e-3), "{:?}", counts);
+            assert!(close(counts.iter().sum::<f32>(), samples), "{:?}", counts);
+        }
+        assert_eq!(stats.slope_histogram.bin_edges[18], 90.0);
+        assert_eq!(stats.aspect_histogram.bin_edges[0], -22.5);
+    }
+}